@@ -1,6 +1,7 @@
 extern crate walkdir;
 
 use std::env;
+use std::fmt::Write;
 use std::fs::{self, DirBuilder};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
@@ -13,10 +14,17 @@ fn main() {
         .expect("Failed to find target directory")
         .join(env::var("PROFILE").unwrap());
 
-    copy(
-        &manifest_dir.join("assets"),
-        &executable_path.join("assets"),
-    );
+    if env::var_os("CARGO_FEATURE_EMBEDDED_ASSETS").is_some() {
+        embed(
+            &manifest_dir.join("assets"),
+            &out_dir.join("embedded_assets.rs"),
+        );
+    } else {
+        copy(
+            &manifest_dir.join("assets"),
+            &executable_path.join("assets"),
+        );
+    }
 }
 
 fn locate_target_dir_from_output_dir(mut target_dir_search: &Path) -> Option<&Path> {
@@ -53,3 +61,35 @@ fn copy(from: &Path, to: &Path) {
         }
     }
 }
+
+/// Writes a sorted `(resource name, contents)` table of every file under
+/// `from`, with the contents pulled in by `include_bytes!`.
+fn embed(from: &Path, to: &Path) {
+    println!("cargo:rerun-if-changed={}", from.display());
+
+    let mut files = Vec::new();
+    for entry in WalkDir::new(from) {
+        let entry = entry.unwrap();
+        println!("cargo:rerun-if-changed={}", entry.path().display());
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        let rel_path = entry.path().strip_prefix(from).unwrap();
+        let name = rel_path
+            .components()
+            .map(|c| c.as_os_str().to_str().expect("Asset path is not UTF-8"))
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push((name, entry.path().to_path_buf()));
+    }
+    files.sort();
+
+    let mut table = String::from("pub static ASSETS: &[(&str, &[u8])] = &[\n");
+    for (name, path) in files {
+        let path = path.to_str().expect("Asset path is not UTF-8");
+        writeln!(table, "    ({:?}, include_bytes!({:?})),", name, path).unwrap();
+    }
+    table.push_str("];\n");
+    fs::write(to, table).expect("Failed to write embedded asset table");
+}
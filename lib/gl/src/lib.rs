@@ -1,9 +1,11 @@
 mod bindings {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
+mod state;
 
 pub use crate::bindings::*;
 pub use crate::bindings::Gl as InnerGl;
+pub use crate::state::{BindState, BindStats};
 
 use std::rc::Rc;
 use std::ops::Deref;
@@ -11,6 +13,7 @@ use std::ops::Deref;
 #[derive(Clone)]
 pub struct Gl {
     inner: Rc<bindings::Gl>,
+    state: Rc<BindState>,
 }
 
 impl Gl {
@@ -20,6 +23,51 @@ impl Gl {
     {
         Gl {
             inner: Rc::new(bindings::Gl::load_with(loadfn)),
+            state: Rc::new(BindState::new()),
+        }
+    }
+
+    /// Bind state shared by every clone of this context.
+    pub fn state(&self) -> &BindState {
+        &self.state
+    }
+
+    pub fn use_program(&self, program: types::GLuint) {
+        if self.state.set_program(program) {
+            unsafe { self.inner.UseProgram(program) }
+        }
+    }
+
+    pub fn bind_vertex_array(&self, vertex_array: types::GLuint) {
+        if self.state.set_vertex_array(vertex_array) {
+            unsafe { self.inner.BindVertexArray(vertex_array) }
+        }
+    }
+
+    pub fn bind_program_pipeline(&self, pipeline: types::GLuint) {
+        if self.state.set_program_pipeline(pipeline) {
+            unsafe { self.inner.BindProgramPipeline(pipeline) }
+        }
+    }
+
+    pub fn bind_buffer(&self, target: types::GLenum, buffer: types::GLuint) {
+        if self.state.set_buffer(target, buffer) {
+            unsafe { self.inner.BindBuffer(target, buffer) }
+        }
+    }
+
+    pub fn bind_texture_unit(&self, unit: types::GLuint, target: types::GLenum, texture: types::GLuint) {
+        if self.state.set_texture(unit, target, texture) {
+            if self.state.set_active_texture_unit(unit) {
+                unsafe { self.inner.ActiveTexture(TEXTURE0 + unit) }
+            }
+            unsafe { self.inner.BindTexture(target, texture) }
+        }
+    }
+
+    pub fn bind_sampler(&self, unit: types::GLuint, sampler: types::GLuint) {
+        if self.state.set_sampler(unit, sampler) {
+            unsafe { self.inner.BindSampler(unit, sampler) }
         }
     }
 }
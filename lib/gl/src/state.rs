@@ -0,0 +1,278 @@
+use crate::types::{GLenum, GLuint};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+/// Counters of bind calls that went through the state cache.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BindStats {
+    pub issued: u64,
+    pub skipped: u64,
+}
+
+/// Tracks which objects are bound on a single GL context.
+///
+/// A `None` entry means the binding is unknown (never set through the cache,
+/// or invalidated), so the next bind is always issued.
+#[derive(Default)]
+pub struct BindState {
+    program: Cell<Option<GLuint>>,
+    vertex_array: Cell<Option<GLuint>>,
+    program_pipeline: Cell<Option<GLuint>>,
+    buffers: RefCell<HashMap<GLenum, GLuint>>,
+    active_texture_unit: Cell<Option<GLuint>>,
+    textures: RefCell<HashMap<(GLuint, GLenum), GLuint>>,
+    samplers: RefCell<HashMap<GLuint, GLuint>>,
+    stats: Cell<BindStats>,
+}
+
+impl BindState {
+    pub fn new() -> BindState {
+        BindState::default()
+    }
+
+    pub fn stats(&self) -> BindStats {
+        self.stats.get()
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats.set(BindStats::default());
+    }
+
+    /// Forgets every cached binding. Call this after binding objects through
+    /// the raw bindings so the cache does not skip a call it should issue.
+    pub fn invalidate(&self) {
+        self.program.set(None);
+        self.vertex_array.set(None);
+        self.program_pipeline.set(None);
+        self.buffers.borrow_mut().clear();
+        self.active_texture_unit.set(None);
+        self.textures.borrow_mut().clear();
+        self.samplers.borrow_mut().clear();
+    }
+
+    pub fn forget_program(&self, program: GLuint) {
+        if self.program.get() == Some(program) {
+            self.program.set(None);
+        }
+    }
+
+    pub fn forget_vertex_array(&self, vertex_array: GLuint) {
+        if self.vertex_array.get() == Some(vertex_array) {
+            // deleting the bound VAO reverts the binding to 0
+            self.vertex_array.set(Some(0));
+            self.buffers.borrow_mut().remove(&crate::ELEMENT_ARRAY_BUFFER);
+        }
+    }
+
+    pub fn forget_program_pipeline(&self, pipeline: GLuint) {
+        if self.program_pipeline.get() == Some(pipeline) {
+            // deleting the bound pipeline reverts the binding to 0
+            self.program_pipeline.set(Some(0));
+        }
+    }
+
+    pub fn forget_buffer(&self, buffer: GLuint) {
+        self.buffers.borrow_mut().retain(|_, bound| *bound != buffer);
+    }
+
+    pub fn forget_texture(&self, texture: GLuint) {
+        self.textures.borrow_mut().retain(|_, bound| *bound != texture);
+    }
+
+    pub fn forget_sampler(&self, sampler: GLuint) {
+        self.samplers.borrow_mut().retain(|_, bound| *bound != sampler);
+    }
+
+    pub(crate) fn set_program(&self, program: GLuint) -> bool {
+        self.record(self.program.replace(Some(program)) != Some(program))
+    }
+
+    pub(crate) fn set_vertex_array(&self, vertex_array: GLuint) -> bool {
+        let changed = self.vertex_array.replace(Some(vertex_array)) != Some(vertex_array);
+        if changed {
+            // the element array binding is part of the VAO state
+            self.buffers.borrow_mut().remove(&crate::ELEMENT_ARRAY_BUFFER);
+        }
+        self.record(changed)
+    }
+
+    pub(crate) fn set_program_pipeline(&self, pipeline: GLuint) -> bool {
+        self.record(self.program_pipeline.replace(Some(pipeline)) != Some(pipeline))
+    }
+
+    pub(crate) fn set_buffer(&self, target: GLenum, buffer: GLuint) -> bool {
+        let previous = self.buffers.borrow_mut().insert(target, buffer);
+        self.record(previous != Some(buffer))
+    }
+
+    pub(crate) fn set_active_texture_unit(&self, unit: GLuint) -> bool {
+        // not counted: switching units is a side effect of a texture bind
+        self.active_texture_unit.replace(Some(unit)) != Some(unit)
+    }
+
+    pub(crate) fn set_texture(&self, unit: GLuint, target: GLenum, texture: GLuint) -> bool {
+        let previous = self.textures.borrow_mut().insert((unit, target), texture);
+        self.record(previous != Some(texture))
+    }
+
+    pub(crate) fn set_sampler(&self, unit: GLuint, sampler: GLuint) -> bool {
+        let previous = self.samplers.borrow_mut().insert(unit, sampler);
+        self.record(previous != Some(sampler))
+    }
+
+    fn record(&self, issue: bool) -> bool {
+        let mut stats = self.stats.get();
+        if issue {
+            stats.issued += 1;
+        } else {
+            stats.skipped += 1;
+        }
+        self.stats.set(stats);
+        issue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_bind_is_skipped() {
+        let state = BindState::new();
+        assert!(state.set_program(1));
+        assert!(!state.set_program(1));
+        assert!(state.set_program(2));
+        assert_eq!(
+            state.stats(),
+            BindStats {
+                issued: 2,
+                skipped: 1
+            }
+        );
+
+        state.reset_stats();
+        assert_eq!(state.stats(), BindStats::default());
+    }
+
+    #[test]
+    fn deleting_bound_program_pipeline_reverts_to_zero() {
+        let state = BindState::new();
+        assert!(state.set_program_pipeline(3));
+        assert!(!state.set_program_pipeline(3));
+        state.forget_program_pipeline(2);
+        assert!(!state.set_program_pipeline(3));
+        state.forget_program_pipeline(3);
+        assert!(!state.set_program_pipeline(0));
+        state.invalidate();
+        assert!(state.set_program_pipeline(0));
+    }
+
+    #[test]
+    fn buffers_are_tracked_per_target() {
+        let state = BindState::new();
+        assert!(state.set_buffer(crate::ARRAY_BUFFER, 3));
+        assert!(state.set_buffer(crate::UNIFORM_BUFFER, 3));
+        assert!(!state.set_buffer(crate::ARRAY_BUFFER, 3));
+    }
+
+    #[test]
+    fn vertex_array_change_forgets_element_buffer() {
+        let state = BindState::new();
+        state.set_vertex_array(1);
+        state.set_buffer(crate::ELEMENT_ARRAY_BUFFER, 5);
+        state.set_buffer(crate::ARRAY_BUFFER, 6);
+
+        state.set_vertex_array(2);
+        assert!(state.set_buffer(crate::ELEMENT_ARRAY_BUFFER, 5));
+        assert!(!state.set_buffer(crate::ARRAY_BUFFER, 6));
+    }
+
+    #[test]
+    fn deleting_bound_vertex_array_reverts_to_zero() {
+        let state = BindState::new();
+        state.set_vertex_array(4);
+        state.forget_vertex_array(4);
+        assert!(!state.set_vertex_array(0));
+    }
+
+    #[test]
+    fn forgotten_objects_are_rebound() {
+        let state = BindState::new();
+        state.set_program(1);
+        state.set_buffer(crate::ARRAY_BUFFER, 2);
+        state.set_texture(0, crate::TEXTURE_2D, 3);
+        state.set_sampler(0, 4);
+
+        state.forget_program(1);
+        state.forget_buffer(2);
+        state.forget_texture(3);
+        state.forget_sampler(4);
+
+        assert!(state.set_program(1));
+        assert!(state.set_buffer(crate::ARRAY_BUFFER, 2));
+        assert!(state.set_texture(0, crate::TEXTURE_2D, 3));
+        assert!(state.set_sampler(0, 4));
+    }
+
+    #[test]
+    fn forgetting_an_unbound_object_keeps_bindings() {
+        let state = BindState::new();
+        state.set_program(1);
+        state.set_texture(0, crate::TEXTURE_2D, 3);
+
+        state.forget_program(2);
+        state.forget_texture(7);
+
+        assert!(!state.set_program(1));
+        assert!(!state.set_texture(0, crate::TEXTURE_2D, 3));
+    }
+
+    #[test]
+    fn textures_are_tracked_per_unit_and_target() {
+        let state = BindState::new();
+        assert!(state.set_texture(0, crate::TEXTURE_2D, 3));
+        assert!(state.set_texture(1, crate::TEXTURE_2D, 3));
+        assert!(state.set_texture(0, crate::TEXTURE_CUBE_MAP, 3));
+        assert!(!state.set_texture(0, crate::TEXTURE_2D, 3));
+    }
+
+    #[test]
+    fn invalidate_forgets_everything() {
+        let state = BindState::new();
+        state.set_program(1);
+        state.set_vertex_array(2);
+        state.set_buffer(crate::ARRAY_BUFFER, 3);
+        state.set_active_texture_unit(0);
+        state.set_texture(0, crate::TEXTURE_2D, 4);
+        state.set_sampler(0, 5);
+
+        state.invalidate();
+
+        assert!(state.set_program(1));
+        assert!(state.set_vertex_array(2));
+        assert!(state.set_buffer(crate::ARRAY_BUFFER, 3));
+        assert!(state.set_active_texture_unit(0));
+        assert!(state.set_texture(0, crate::TEXTURE_2D, 4));
+        assert!(state.set_sampler(0, 5));
+    }
+
+    #[test]
+    fn samplers_are_cached_per_unit() {
+        let state = BindState::new();
+        assert!(state.set_sampler(0, 4));
+        assert!(!state.set_sampler(0, 4));
+        assert!(state.set_sampler(1, 4));
+        assert!(state.set_sampler(0, 5));
+        assert!(!state.set_sampler(1, 4));
+        assert_eq!(
+            state.stats(),
+            BindStats {
+                issued: 3,
+                skipped: 2
+            }
+        );
+
+        state.forget_sampler(7);
+        assert!(!state.set_sampler(0, 5));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::render_gl::{ComputeProgram, Program, SeparableProgram, Shader, Texture2D};
+use crate::resources::{FromResource, Resources};
+use gl;
+use std::any::{self, Any, TypeId};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::rc::{Rc, Weak};
+
+mod loader;
+
+pub use self::loader::{BackgroundAsset, Handle, Loader, ProgramSources, Progress};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "{} {} depends on itself", type_name, name)]
+    LoadCycle {
+        name: String,
+        type_name: &'static str,
+    },
+    #[fail(display = "Failed to spawn asset loader thread")]
+    SpawnWorker(#[cause] io::Error),
+}
+
+/// Something an `AssetCache` can load by resource name.
+pub trait Asset: Sized + 'static {
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Self, failure::Error>;
+}
+
+impl<T: FromResource + 'static> Asset for T {
+    fn load(_gl: &gl::Gl, res: &Resources, name: &str) -> Result<Self, failure::Error> {
+        Ok(res.load(name)?)
+    }
+}
+
+impl Asset for Shader {
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Self, failure::Error> {
+        Ok(Shader::from_res(gl, res, name)?)
+    }
+}
+
+impl Asset for Program {
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Self, failure::Error> {
+        Ok(Program::from_res(gl, res, name)?)
+    }
+}
+
+impl Asset for SeparableProgram {
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Self, failure::Error> {
+        Ok(SeparableProgram::from_res(gl, res, name)?)
+    }
+}
+
+impl Asset for ComputeProgram {
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Self, failure::Error> {
+        Ok(ComputeProgram::from_res(gl, res, name)?)
+    }
+}
+
+impl Asset for Texture2D {
+    fn load(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Self, failure::Error> {
+        Ok(Texture2D::from_res(gl, res, name)?)
+    }
+}
+
+/// One asset still held by at least one handle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resident {
+    pub name: String,
+    pub type_name: &'static str,
+    pub handles: usize,
+}
+
+struct Entry {
+    type_name: &'static str,
+    value: Weak<dyn Any>,
+}
+
+/// Loads each (type, resource name) pair once and hands out shared handles
+/// to it. The cache only keeps weak references, so an asset is dropped as
+/// soon as its last handle goes away and is loaded again on the next request.
+///
+/// The cache lives on the GL thread, so the only overlapping requests are
+/// re-entrant ones from inside an asset's `load`; those are reported as
+/// `LoadCycle`. Loading from other threads goes through `Loader`.
+pub struct AssetCache {
+    gl: gl::Gl,
+    entries: RefCell<HashMap<(TypeId, String), Entry>>,
+    loading: RefCell<HashSet<(TypeId, String)>>,
+}
+
+impl AssetCache {
+    pub fn new(gl: &gl::Gl) -> AssetCache {
+        AssetCache {
+            gl: gl.clone(),
+            entries: RefCell::new(HashMap::new()),
+            loading: RefCell::new(HashSet::new()),
+        }
+    }
+
+    pub fn get<T: Asset>(&self, res: &Resources, name: &str) -> Result<Rc<T>, failure::Error> {
+        let key = (TypeId::of::<T>(), name.to_owned());
+        if let Some(asset) = self.lookup::<T>(&key) {
+            return Ok(asset);
+        }
+
+        // an asset whose loader asks the cache for itself would otherwise
+        // recurse until the stack runs out
+        if !self.loading.borrow_mut().insert(key.clone()) {
+            return Err(Error::LoadCycle {
+                name: name.into(),
+                type_name: any::type_name::<T>(),
+            }
+            .into());
+        }
+        let result = T::load(&self.gl, res, name);
+        self.loading.borrow_mut().remove(&key);
+
+        let asset = Rc::new(result?);
+        let value: Rc<dyn Any> = asset.clone();
+        // a miss is the only time the map grows, so drop dead entries here
+        // rather than letting them pile up between `len` calls
+        self.purge();
+        self.entries.borrow_mut().insert(
+            key,
+            Entry {
+                type_name: any::type_name::<T>(),
+                value: Rc::downgrade(&value),
+            },
+        );
+        Ok(asset)
+    }
+
+    /// The asset if it is resident, without loading it.
+    pub fn get_loaded<T: Asset>(&self, name: &str) -> Option<Rc<T>> {
+        self.lookup::<T>(&(TypeId::of::<T>(), name.to_owned()))
+    }
+
+    /// Every resident asset, sorted by name.
+    pub fn resident(&self) -> Vec<Resident> {
+        self.purge();
+        let mut resident: Vec<Resident> = self
+            .entries
+            .borrow()
+            .iter()
+            .map(|((_, name), entry)| Resident {
+                name: name.clone(),
+                type_name: entry.type_name,
+                handles: entry.value.strong_count(),
+            })
+            .collect();
+        resident.sort_by(|a, b| (&a.name, a.type_name).cmp(&(&b.name, b.type_name)));
+        resident
+    }
+
+    pub fn len(&self) -> usize {
+        self.purge();
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lookup<T: Asset>(&self, key: &(TypeId, String)) -> Option<Rc<T>> {
+        let value = self.entries.borrow().get(key)?.value.upgrade()?;
+        value.downcast::<T>().ok()
+    }
+
+    /// Forgets entries whose asset has been dropped.
+    fn purge(&self) {
+        self.entries
+            .borrow_mut()
+            .retain(|_, entry| entry.value.strong_count() > 0);
+    }
+}
@@ -0,0 +1,360 @@
+use crate::assets::Error;
+use crate::image::Image;
+use crate::render_gl::{self, Defines, Program, Shader, Texture2D};
+use crate::resources::{FromResource, Resources};
+use failure::err_msg;
+use gl;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// An asset loaded in two steps: reading and decoding on a worker thread,
+/// then creating GL objects on the thread that owns the context.
+pub trait BackgroundAsset: Sized + 'static {
+    /// Everything `finalize` needs, produced without touching GL.
+    type Decoded: Send + 'static;
+
+    fn decode(res: &Resources, name: &str) -> Result<Self::Decoded, failure::Error>;
+
+    fn finalize(gl: &gl::Gl, name: &str, decoded: Self::Decoded) -> Result<Self, failure::Error>;
+}
+
+impl<T: FromResource + Send + 'static> BackgroundAsset for T {
+    type Decoded = T;
+
+    fn decode(res: &Resources, name: &str) -> Result<T, failure::Error> {
+        Ok(res.load(name)?)
+    }
+
+    fn finalize(_gl: &gl::Gl, _name: &str, decoded: T) -> Result<T, failure::Error> {
+        Ok(decoded)
+    }
+}
+
+impl BackgroundAsset for Shader {
+    type Decoded = render_gl::Source;
+
+    fn decode(res: &Resources, name: &str) -> Result<render_gl::Source, failure::Error> {
+        Ok(render_gl::preprocess(res, name)?)
+    }
+
+    fn finalize(
+        gl: &gl::Gl,
+        name: &str,
+        decoded: render_gl::Source,
+    ) -> Result<Shader, failure::Error> {
+        Ok(Shader::from_source(gl, name, &decoded)?)
+    }
+}
+
+/// Preprocessed stages of a program, ready to compile.
+pub struct ProgramSources(Vec<render_gl::StageSource>);
+
+impl BackgroundAsset for Program {
+    type Decoded = ProgramSources;
+
+    fn decode(res: &Resources, name: &str) -> Result<ProgramSources, failure::Error> {
+        let sources = render_gl::load_stage_sources(res, name, &Defines::new(), false)?;
+        Ok(ProgramSources(sources))
+    }
+
+    fn finalize(
+        gl: &gl::Gl,
+        name: &str,
+        decoded: ProgramSources,
+    ) -> Result<Program, failure::Error> {
+        Ok(Program::from_stage_sources(gl, name, &decoded.0, &[])?)
+    }
+}
+
+impl BackgroundAsset for Texture2D {
+    type Decoded = Image;
+
+    fn decode(res: &Resources, name: &str) -> Result<Image, failure::Error> {
+        let mut image: Image = res.load(name)?;
+        image.flip_vertical();
+        Ok(image)
+    }
+
+    fn finalize(gl: &gl::Gl, _name: &str, decoded: Image) -> Result<Texture2D, failure::Error> {
+        Ok(Texture2D::from_image(gl, &decoded, true)?)
+    }
+}
+
+enum State<T> {
+    Pending,
+    Ready(Rc<T>),
+    Failed(String),
+}
+
+/// Shared handle to an asset requested from a `Loader`. It becomes ready
+/// during the `Loader::update` call that finalizes it.
+pub struct Handle<T> {
+    name: Rc<str>,
+    state: Rc<RefCell<State<T>>>,
+}
+
+impl<T> Handle<T> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether loading has finished, successfully or not.
+    pub fn is_done(&self) -> bool {
+        !matches!(*self.state.borrow(), State::Pending)
+    }
+
+    pub fn get(&self) -> Option<Rc<T>> {
+        match *self.state.borrow() {
+            State::Ready(ref asset) => Some(asset.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn error(&self) -> Option<String> {
+        match *self.state.borrow() {
+            State::Failed(ref message) => Some(message.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            name: self.name.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Counts of assets requested from a `Loader` since it was created.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub requested: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+impl Progress {
+    pub fn pending(&self) -> usize {
+        self.requested - self.completed - self.failed
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Share of requests that have finished, between 0 and 1.
+    pub fn fraction(&self) -> f32 {
+        if self.requested == 0 {
+            return 1.0;
+        }
+        (self.completed + self.failed) as f32 / self.requested as f32
+    }
+}
+
+type Decoded = Result<Box<dyn Any + Send>, failure::Error>;
+type Job = Box<dyn FnOnce(&Resources) -> Decoded + Send>;
+/// Runs on the GL thread; returns whether the asset loaded.
+type Finalizer = Box<dyn FnOnce(&gl::Gl, Decoded) -> bool>;
+
+/// Loads assets in the background. Files are read and decoded by a pool of
+/// worker threads; the GL half of loading is queued and run by `update`, which
+/// must be called on the thread that owns the context, typically once per
+/// frame.
+///
+/// Requesting an asset that is still loading or held by a handle returns
+/// another handle to the same load.
+pub struct Loader {
+    gl: gl::Gl,
+    jobs: Option<mpsc::Sender<(u64, Job)>>,
+    results: mpsc::Receiver<(u64, Decoded)>,
+    workers: Vec<thread::JoinHandle<()>>,
+    cancelled: Arc<AtomicBool>,
+    finalizers: HashMap<u64, Finalizer>,
+    decoded: VecDeque<(u64, Decoded)>,
+    requests: HashMap<(TypeId, String), Weak<dyn Any>>,
+    next_id: u64,
+    progress: Progress,
+}
+
+impl Loader {
+    pub fn new(gl: &gl::Gl, res: Arc<Resources>, worker_count: usize) -> Result<Loader, Error> {
+        let (job_sender, job_receiver) = mpsc::channel::<(u64, Job)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let mut loader = Loader {
+            gl: gl.clone(),
+            jobs: Some(job_sender),
+            results,
+            workers: Vec::new(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            finalizers: HashMap::new(),
+            decoded: VecDeque::new(),
+            requests: HashMap::new(),
+            next_id: 0,
+            progress: Progress::default(),
+        };
+        for i in 0..worker_count.max(1) {
+            let res = res.clone();
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            let cancelled = loader.cancelled.clone();
+            // on failure, dropping `loader` stops the workers already running
+            let worker = thread::Builder::new()
+                .name(format!("asset loader {}", i))
+                .spawn(move || worker(&res, &job_receiver, &result_sender, &cancelled))
+                .map_err(Error::SpawnWorker)?;
+            loader.workers.push(worker);
+        }
+        Ok(loader)
+    }
+
+    pub fn load<T: BackgroundAsset>(&mut self, name: &str) -> Handle<T> {
+        let key = (TypeId::of::<T>(), name.to_owned());
+        let existing = self
+            .requests
+            .get(&key)
+            .and_then(|state| state.upgrade())
+            .and_then(|state| state.downcast::<RefCell<State<T>>>().ok());
+        if let Some(state) = existing {
+            return Handle {
+                name: name.into(),
+                state,
+            };
+        }
+
+        let state = Rc::new(RefCell::new(State::Pending));
+        let any_state: Rc<dyn Any> = state.clone();
+        self.requests.insert(key, Rc::downgrade(&any_state));
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.progress.requested += 1;
+
+        let finalize_state = state.clone();
+        let finalize_name = name.to_owned();
+        self.finalizers.insert(
+            id,
+            Box::new(move |gl, decoded| {
+                let asset = decoded.and_then(|decoded| match decoded.downcast::<T::Decoded>() {
+                    Ok(decoded) => T::finalize(gl, &finalize_name, *decoded),
+                    Err(_) => Err(err_msg("decoded asset has the wrong type")),
+                });
+                let (state, loaded) = match asset {
+                    Ok(asset) => (State::Ready(Rc::new(asset)), true),
+                    Err(e) => (State::Failed(crate::failure_to_string(e)), false),
+                };
+                *finalize_state.borrow_mut() = state;
+                loaded
+            }),
+        );
+
+        let job_name = name.to_owned();
+        let job: Job = Box::new(move |res| {
+            T::decode(res, &job_name).map(|decoded| Box::new(decoded) as Box<dyn Any + Send>)
+        });
+        if let Some(ref jobs) = self.jobs {
+            // workers only exit once the sender is dropped
+            let _ = jobs.send((id, job));
+        }
+
+        Handle {
+            name: name.into(),
+            state,
+        }
+    }
+
+    /// Finalizes decoded assets until `budget` has been spent. At least one
+    /// asset is finalized per call when any is waiting, so a budget smaller
+    /// than a single upload still makes progress.
+    pub fn update(&mut self, budget: Duration) -> Progress {
+        let start = Instant::now();
+        self.decoded.extend(self.results.try_iter());
+        while let Some((id, decoded)) = self.decoded.pop_front() {
+            self.finalize(id, decoded);
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        self.requests.retain(|_, state| state.strong_count() > 0);
+        self.progress
+    }
+
+    /// Blocks until every requested asset has loaded or failed.
+    pub fn finish(&mut self) -> Progress {
+        while let Some((id, decoded)) = self.decoded.pop_front() {
+            self.finalize(id, decoded);
+        }
+        while !self.finalizers.is_empty() {
+            match self.results.recv() {
+                Ok((id, decoded)) => self.finalize(id, decoded),
+                Err(_) => break,
+            }
+        }
+        self.requests.retain(|_, state| state.strong_count() > 0);
+        self.progress
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    fn finalize(&mut self, id: u64, decoded: Decoded) {
+        if let Some(finalizer) = self.finalizers.remove(&id) {
+            if finalizer(&self.gl, decoded) {
+                self.progress.completed += 1;
+            } else {
+                self.progress.failed += 1;
+            }
+        }
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        // workers skip whatever is still queued once cancelled; closing the
+        // queue wakes the idle ones, so each exits after its current job
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker(
+    res: &Resources,
+    jobs: &Mutex<mpsc::Receiver<(u64, Job)>>,
+    results: &mpsc::Sender<(u64, Decoded)>,
+    cancelled: &AtomicBool,
+) {
+    loop {
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        let (id, job) = match job {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        if cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // a panicking decoder fails its own asset rather than the worker
+        let decoded = panic::catch_unwind(AssertUnwindSafe(|| job(res)))
+            .unwrap_or_else(|_| Err(err_msg("asset decoder panicked")));
+        if results.send((id, decoded)).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use crate::resources::{self, FromResource};
+
+mod hdr;
+mod pnm;
+mod tga;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Unrecognized image format")]
+    UnknownFormat,
+    #[fail(display = "Invalid {} image: {}", format, message)]
+    Invalid {
+        format: &'static str,
+        message: String,
+    },
+    #[fail(display = "Unsupported {} image: {}", format, message)]
+    Unsupported {
+        format: &'static str,
+        message: String,
+    },
+}
+
+/// Samples of a decoded image, interleaved by channel.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageData {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+/// A decoded image. Rows are stored top row first, as in the files; call
+/// `flip_vertical` before uploading to put GL's bottom-left origin first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// 1 (gray), 2 (gray and alpha), 3 (RGB) or 4 (RGBA).
+    pub channels: u32,
+    pub data: ImageData,
+}
+
+impl Image {
+    /// Decodes PNG, TGA, binary PPM/PGM or Radiance HDR data, recognized by
+    /// its leading bytes. TGA has no signature, so it is tried last.
+    pub fn decode(bytes: &[u8]) -> Result<Image, Error> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            decode_png(bytes)
+        } else if bytes.starts_with(b"P5") || bytes.starts_with(b"P6") {
+            pnm::decode(bytes)
+        } else if bytes.starts_with(b"#?RADIANCE") || bytes.starts_with(b"#?RGBE") {
+            hdr::decode(bytes)
+        } else if tga::is_tga(bytes) {
+            tga::decode(bytes)
+        } else {
+            Err(Error::UnknownFormat)
+        }
+    }
+
+    /// Reverses the order of the rows.
+    pub fn flip_vertical(&mut self) {
+        let row = self.width as usize * self.channels as usize;
+        match self.data {
+            ImageData::U8(ref mut data) => flip_rows(data, row),
+            ImageData::U16(ref mut data) => flip_rows(data, row),
+            ImageData::F32(ref mut data) => flip_rows(data, row),
+        }
+    }
+
+    /// Converts to another channel count. Gray is replicated into RGB, color
+    /// is reduced to gray by Rec. 709 luminance, and a missing alpha channel
+    /// is filled as opaque.
+    pub fn convert(&self, channels: u32) -> Image {
+        assert!((1..=4).contains(&channels), "Invalid channel count");
+        let data = match self.data {
+            ImageData::U8(ref data) => ImageData::U8(convert(data, self.channels, channels)),
+            ImageData::U16(ref data) => ImageData::U16(convert(data, self.channels, channels)),
+            ImageData::F32(ref data) => ImageData::F32(convert(data, self.channels, channels)),
+        };
+        Image {
+            width: self.width,
+            height: self.height,
+            channels,
+            data,
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.channels == 2 || self.channels == 4
+    }
+}
+
+impl FromResource for Image {
+    fn from_resource(name: &str, bytes: Vec<u8>) -> Result<Self, resources::Error> {
+        Image::decode(&bytes).map_err(|e| resources::Error::Parse {
+            name: name.into(),
+            message: e.to_string(),
+        })
+    }
+}
+
+/// Number of samples in an image, checked so that a size read from a file
+/// header cannot overflow.
+fn sample_count(
+    format: &'static str,
+    width: u32,
+    height: u32,
+    channels: u32,
+) -> Result<usize, Error> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(channels as usize))
+        .ok_or_else(|| Error::Invalid {
+            format,
+            message: format!("size {}x{} is too large", width, height),
+        })
+}
+
+fn flip_rows<T>(data: &mut [T], row: usize) {
+    let rows = data.len() / row.max(1);
+    for y in 0..rows / 2 {
+        let (top, bottom) = data.split_at_mut((rows - 1 - y) * row);
+        top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+    }
+}
+
+/// A channel value: `MAX` is full intensity, as used for filled alpha.
+trait Sample: Copy {
+    const MAX: Self;
+
+    fn to_f32(self) -> f32;
+
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Sample for u8 {
+    const MAX: u8 = u8::MAX;
+
+    fn to_f32(self) -> f32 {
+        f32::from(self)
+    }
+
+    fn from_f32(value: f32) -> u8 {
+        value.round() as u8
+    }
+}
+
+impl Sample for u16 {
+    const MAX: u16 = u16::MAX;
+
+    fn to_f32(self) -> f32 {
+        f32::from(self)
+    }
+
+    fn from_f32(value: f32) -> u16 {
+        value.round() as u16
+    }
+}
+
+impl Sample for f32 {
+    const MAX: f32 = 1.0;
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> f32 {
+        value
+    }
+}
+
+fn convert<T: Sample>(data: &[T], from: u32, to: u32) -> Vec<T> {
+    let (from, to) = (from as usize, to as usize);
+    let mut result = Vec::with_capacity(data.len() / from * to);
+    for pixel in data.chunks_exact(from) {
+        let (gray, rgb, alpha) = match from {
+            1 => (pixel[0], [pixel[0]; 3], T::MAX),
+            2 => (pixel[0], [pixel[0]; 3], pixel[1]),
+            _ => {
+                let luminance = 0.2126 * pixel[0].to_f32()
+                    + 0.7152 * pixel[1].to_f32()
+                    + 0.0722 * pixel[2].to_f32();
+                let alpha = if from == 4 { pixel[3] } else { T::MAX };
+                (
+                    T::from_f32(luminance),
+                    [pixel[0], pixel[1], pixel[2]],
+                    alpha,
+                )
+            }
+        };
+        match to {
+            1 => result.push(gray),
+            2 => result.extend_from_slice(&[gray, alpha]),
+            3 => result.extend_from_slice(&rgb),
+            _ => {
+                result.extend_from_slice(&rgb);
+                result.push(alpha);
+            }
+        }
+    }
+    result
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Image, Error> {
+    let invalid = |e: png::DecodingError| Error::Invalid {
+        format: "PNG",
+        message: e.to_string(),
+    };
+
+    let mut decoder = png::Decoder::new(bytes);
+    // palettes, transparency chunks and sub-byte gray all become plain
+    // 8-bit channels; 16-bit samples are kept
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+    buffer.truncate(info.buffer_size());
+
+    let channels = info.color_type.samples() as u32;
+    let data = match info.bit_depth {
+        png::BitDepth::Eight => ImageData::U8(buffer),
+        png::BitDepth::Sixteen => ImageData::U16(
+            buffer
+                .chunks_exact(2)
+                .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+                .collect(),
+        ),
+        depth => {
+            return Err(Error::Unsupported {
+                format: "PNG",
+                message: format!("{:?} bit samples after expansion", depth),
+            })
+        }
+    };
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        channels,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(width: u32, height: u32, data: Vec<u8>) -> Image {
+        Image {
+            width,
+            height,
+            channels: 1,
+            data: ImageData::U8(data),
+        }
+    }
+
+    #[test]
+    fn decodes_by_signature() {
+        let image = Image::decode(b"P5 2 1 255\n\x01\x02").unwrap();
+        assert_eq!(image, gray(2, 1, vec![1, 2]));
+    }
+
+    #[test]
+    fn rejects_unknown_and_empty_data() {
+        assert!(matches!(Image::decode(b""), Err(Error::UnknownFormat)));
+        assert!(matches!(
+            Image::decode(b"GIF89a"),
+            Err(Error::UnknownFormat)
+        ));
+        assert!(matches!(
+            Image::decode(b"\x89PNG\r\n\x1a\n"),
+            Err(Error::Invalid { format: "PNG", .. })
+        ));
+    }
+
+    #[test]
+    fn reports_decode_errors_as_parse_errors() {
+        match Image::from_resource("textures/a.tga", b"P6 1 1 255\n".to_vec()) {
+            Err(resources::Error::Parse { name, .. }) => assert_eq!(name, "textures/a.tga"),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn flips_rows() {
+        let mut image = gray(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        image.flip_vertical();
+        assert_eq!(image.data, ImageData::U8(vec![5, 6, 3, 4, 1, 2]));
+
+        let mut image = Image {
+            width: 1,
+            height: 2,
+            channels: 2,
+            data: ImageData::F32(vec![0.0, 0.5, 1.0, 1.5]),
+        };
+        image.flip_vertical();
+        assert_eq!(image.data, ImageData::F32(vec![1.0, 1.5, 0.0, 0.5]));
+    }
+
+    #[test]
+    fn converts_gray_to_color() {
+        let image = gray(2, 1, vec![10, 20]);
+        assert_eq!(
+            image.convert(4).data,
+            ImageData::U8(vec![10, 10, 10, 255, 20, 20, 20, 255])
+        );
+        assert_eq!(
+            image.convert(3).data,
+            ImageData::U8(vec![10, 10, 10, 20, 20, 20])
+        );
+        assert_eq!(image.convert(2).data, ImageData::U8(vec![10, 255, 20, 255]));
+        assert!(!image.has_alpha());
+        assert!(image.convert(2).has_alpha());
+    }
+
+    #[test]
+    fn converts_color_to_gray_by_luminance() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            channels: 4,
+            data: ImageData::U16(vec![65535, 0, 0, 100, 0, 65535, 0, 200]),
+        };
+        let converted = image.convert(2);
+        assert_eq!((converted.width, converted.height), (2, 1));
+        assert_eq!(converted.channels, 2);
+        assert_eq!(converted.data, ImageData::U16(vec![13933, 100, 46871, 200]));
+        assert_eq!(
+            image.convert(3).data,
+            ImageData::U16(vec![65535, 0, 0, 0, 65535, 0])
+        );
+    }
+
+    #[test]
+    fn fills_missing_alpha_as_opaque() {
+        let image = Image {
+            width: 1,
+            height: 1,
+            channels: 3,
+            data: ImageData::F32(vec![0.25, 0.5, 0.75]),
+        };
+        assert_eq!(
+            image.convert(4).data,
+            ImageData::F32(vec![0.25, 0.5, 0.75, 1.0])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid channel count")]
+    fn rejects_invalid_channel_count() {
+        gray(1, 1, vec![0]).convert(5);
+    }
+
+    #[test]
+    fn checks_sample_count_overflow() {
+        assert_eq!(sample_count("TGA", 3, 2, 4).unwrap(), 24);
+        assert!(matches!(
+            sample_count("TGA", u32::MAX, u32::MAX, 4),
+            Err(Error::Invalid { format: "TGA", .. })
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+use crate::image::{sample_count, Error, Image, ImageData};
+
+const FORMAT: &str = "Radiance HDR";
+
+/// Decodes a Radiance RGBE image into 32-bit float RGB. Both flat and
+/// run-length encoded scanlines are read; the older RLE scheme that predates
+/// per-channel runs is not.
+pub fn decode(bytes: &[u8]) -> Result<Image, Error> {
+    let mut pos = 0;
+    let mut format_ok = false;
+    loop {
+        let line = read_line(bytes, &mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(Error::Unsupported {
+                    format: FORMAT,
+                    message: format!("pixel format {}", format),
+                });
+            }
+            format_ok = true;
+        }
+    }
+    if !format_ok {
+        return Err(invalid("missing FORMAT line"));
+    }
+
+    let resolution = read_line(bytes, &mut pos)?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (width, height, bottom_up) = match fields[..] {
+        ["-Y", height, "+X", width] => (width, height, false),
+        ["+Y", height, "+X", width] => (width, height, true),
+        _ => {
+            return Err(Error::Unsupported {
+                format: FORMAT,
+                message: format!("image orientation {}", resolution),
+            })
+        }
+    };
+    let parse = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|&value| value > 0)
+            .ok_or_else(|| invalid("invalid resolution"))
+    };
+    let (width, height) = (parse(width)?, parse(height)?);
+
+    let samples = sample_count(FORMAT, width, height, 3)?;
+    // every scanline takes at least four bytes, and one too wide to be run-length
+    // encoded takes four per pixel
+    let remaining = bytes.len() - pos;
+    if height as usize > remaining / 4 || (width >= 0x8000 && width as usize > remaining / 4) {
+        return Err(invalid("pixel data is truncated"));
+    }
+
+    // run-length encoded data can be far smaller than the image, so reserve
+    // what flat scanlines would hold and let the rest grow as rows decode
+    let mut data = Vec::with_capacity(samples.min(remaining / 4 * 3));
+    let mut scanline = vec![[0u8; 4]; width as usize];
+    for _ in 0..height {
+        read_scanline(bytes, &mut pos, &mut scanline)?;
+        for rgbe in scanline.iter() {
+            data.extend_from_slice(&rgbe_to_rgb(*rgbe));
+        }
+    }
+
+    let mut image = Image {
+        width,
+        height,
+        channels: 3,
+        data: ImageData::F32(data),
+    };
+    if bottom_up {
+        image.flip_vertical();
+    }
+    Ok(image)
+}
+
+fn read_line<'a>(bytes: &'a [u8], pos: &mut usize) -> Result<&'a str, Error> {
+    let rest = bytes
+        .get(*pos..)
+        .ok_or_else(|| invalid("header is truncated"))?;
+    let end = rest
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| invalid("header is truncated"))?;
+    *pos += end + 1;
+    std::str::from_utf8(&rest[..end])
+        .map(|line| line.trim_end_matches('\r'))
+        .map_err(|_| invalid("header is not text"))
+}
+
+fn read_scanline(bytes: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), Error> {
+    let truncated = || invalid("pixel data is truncated");
+    let width = scanline.len();
+    let start = bytes.get(*pos..*pos + 4).ok_or_else(truncated)?;
+
+    // run-length encoded scanlines start with 2, 2 and the width; anything
+    // else is a flat scanline
+    let encoded = (8..0x8000).contains(&width)
+        && start[0] == 2
+        && start[1] == 2
+        && usize::from(u16::from_be_bytes([start[2], start[3]])) == width;
+    if !encoded {
+        for pixel in scanline.iter_mut() {
+            let rgbe = bytes.get(*pos..*pos + 4).ok_or_else(truncated)?;
+            pixel.copy_from_slice(rgbe);
+            *pos += 4;
+        }
+        return Ok(());
+    }
+
+    *pos += 4;
+    // the four channels follow one after another, each encoded separately
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes.get(*pos).ok_or_else(truncated)? as usize;
+            *pos += 1;
+            if count > 128 {
+                let count = count - 128;
+                let value = *bytes.get(*pos).ok_or_else(truncated)?;
+                *pos += 1;
+                if count > width - x {
+                    return Err(invalid("run exceeds scanline"));
+                }
+                for pixel in scanline[x..x + count].iter_mut() {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || count > width - x {
+                    return Err(invalid("run exceeds scanline"));
+                }
+                let values = bytes.get(*pos..*pos + count).ok_or_else(truncated)?;
+                *pos += count;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rgbe_to_rgb(rgbe: [u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+    // mantissas are fractions of 256 scaled by 2^(exponent - 128)
+    let scale = 2f32.powi(i32::from(rgbe[3]) - 136);
+    [
+        f32::from(rgbe[0]) * scale,
+        f32::from(rgbe[1]) * scale,
+        f32::from(rgbe[2]) * scale,
+    ]
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Invalid {
+        format: FORMAT,
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(resolution: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut bytes = b"#?RADIANCE\n# made by hand\nFORMAT=32-bit_rle_rgbe\n\n".to_vec();
+        bytes.extend_from_slice(resolution.as_bytes());
+        bytes.push(b'\n');
+        bytes.extend_from_slice(pixels);
+        bytes
+    }
+
+    fn invalid_message(result: Result<Image, Error>) -> String {
+        match result {
+            Err(Error::Invalid { format, message }) => {
+                assert_eq!(format, FORMAT);
+                message
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_flat_scanlines() {
+        let image = decode(&file("-Y 1 +X 2", &[128, 64, 0, 129, 255, 255, 255, 0])).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 1, 3));
+        assert_eq!(
+            image.data,
+            ImageData::F32(vec![1.0, 0.5, 0.0, 0.0, 0.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn flips_bottom_up_images() {
+        let image = decode(&file("+Y 2 +X 1", &[128, 0, 0, 129, 0, 128, 0, 129])).unwrap();
+        assert_eq!(
+            image.data,
+            ImageData::F32(vec![0.0, 1.0, 0.0, 1.0, 0.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn decodes_run_length_encoded_scanlines() {
+        let mut pixels = vec![2, 2, 0, 8];
+        // red as one run, green as literal values, blue and exponent as runs
+        pixels.extend_from_slice(&[0x88, 128]);
+        pixels.push(8);
+        pixels.extend_from_slice(&[0, 32, 64, 96, 128, 160, 192, 224]);
+        pixels.extend_from_slice(&[0x88, 0, 0x88, 129]);
+
+        let image = decode(&file("-Y 1 +X 8", &pixels)).unwrap();
+        let expected: Vec<f32> = (0..8)
+            .flat_map(|x| vec![1.0, x as f32 * 0.25, 0.0])
+            .collect();
+        assert_eq!(image.data, ImageData::F32(expected));
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert_eq!(
+            invalid_message(decode(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n")),
+            "header is truncated"
+        );
+        assert_eq!(
+            invalid_message(decode(&file("-Y 2 +X 1", &[128, 0, 0, 129, 0, 0]))),
+            "pixel data is truncated"
+        );
+        assert_eq!(
+            invalid_message(decode(&file("-Y 1 +X 8", &[2, 2, 0, 8, 0x88]))),
+            "pixel data is truncated"
+        );
+        assert_eq!(
+            invalid_message(decode(&file("-Y 1 +X 8", &[2, 2, 0, 8, 0x89, 0]))),
+            "run exceeds scanline"
+        );
+    }
+
+    #[test]
+    fn rejects_huge_sizes_without_reserving() {
+        assert_eq!(
+            invalid_message(decode(&file("-Y 200000 +X 200000", &[0; 16]))),
+            "pixel data is truncated"
+        );
+        assert_eq!(
+            invalid_message(decode(&file("-Y 1 +X 200000", &[0; 16]))),
+            "pixel data is truncated"
+        );
+        assert_eq!(
+            invalid_message(decode(&file("-Y 4294967295 +X 4294967295", &[]))),
+            "size 4294967295x4294967295 is too large"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert_eq!(
+            invalid_message(decode(b"#?RADIANCE\n\n-Y 1 +X 1\n\0\0\0\0")),
+            "missing FORMAT line"
+        );
+        assert!(matches!(
+            decode(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0"),
+            Err(Error::Unsupported { .. })
+        ));
+        assert!(matches!(
+            decode(&file("+X 1 -Y 1", &[0; 4])),
+            Err(Error::Unsupported { .. })
+        ));
+        assert_eq!(
+            invalid_message(decode(&file("-Y 0 +X 1", &[]))),
+            "invalid resolution"
+        );
+        assert_eq!(
+            invalid_message(decode(&file("-Y 1 +X -1", &[]))),
+            "invalid resolution"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::image::{sample_count, Error, Image, ImageData};
+
+const FORMAT: &str = "PPM/PGM";
+
+/// Decodes binary PGM (`P5`) and PPM (`P6`). Samples with a maximum value
+/// other than 255 or 65535 are rescaled to the full range.
+pub fn decode(bytes: &[u8]) -> Result<Image, Error> {
+    let channels = match bytes.get(..2) {
+        Some(b"P5") => 1,
+        Some(b"P6") => 3,
+        _ => return Err(invalid("not a binary PPM or PGM file")),
+    };
+
+    let mut pos = 2;
+    let width = header_value(bytes, &mut pos)?;
+    let height = header_value(bytes, &mut pos)?;
+    let max_value = header_value(bytes, &mut pos)?;
+    // exactly one whitespace byte separates the header from the samples
+    pos += 1;
+
+    if width == 0 || height == 0 {
+        return Err(invalid("image is empty"));
+    }
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("maximum value must be between 1 and 65535"));
+    }
+
+    let sample_size = if max_value < 256 { 1 } else { 2 };
+    let samples = sample_count(FORMAT, width, height, channels)?;
+    let size = sample_count(FORMAT, width, height, channels * sample_size)?;
+    let data = bytes
+        .get(pos..)
+        .filter(|data| data.len() >= size)
+        .ok_or_else(|| invalid("file is truncated"))?;
+
+    let data = if sample_size == 1 {
+        let data = &data[..samples];
+        if max_value == 255 {
+            ImageData::U8(data.to_vec())
+        } else {
+            ImageData::U8(
+                data.iter()
+                    .map(|&v| ((u32::from(v) * 255 + max_value / 2) / max_value).min(255) as u8)
+                    .collect(),
+            )
+        }
+    } else {
+        ImageData::U16(
+            data[..size]
+                .chunks_exact(2)
+                .map(|v| u32::from(u16::from_be_bytes([v[0], v[1]])))
+                .map(|v| ((v * 65535 + max_value / 2) / max_value).min(65535) as u16)
+                .collect(),
+        )
+    };
+    Ok(Image {
+        width,
+        height,
+        channels,
+        data,
+    })
+}
+
+/// Reads the next decimal header field, skipping whitespace and `#`
+/// comments that run to the end of the line.
+fn header_value(bytes: &[u8], pos: &mut usize) -> Result<u32, Error> {
+    loop {
+        match bytes.get(*pos) {
+            Some(b'#') => {
+                while bytes.get(*pos).is_some_and(|&b| b != b'\n') {
+                    *pos += 1;
+                }
+            }
+            Some(b) if b.is_ascii_whitespace() => *pos += 1,
+            Some(_) => break,
+            None => return Err(invalid("header is truncated")),
+        }
+    }
+
+    let start = *pos;
+    while bytes.get(*pos).is_some_and(|b| b.is_ascii_digit()) {
+        *pos += 1;
+    }
+    std::str::from_utf8(&bytes[start..*pos])
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| invalid("header field is not a number"))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Invalid {
+        format: FORMAT,
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_message(result: Result<Image, Error>) -> String {
+        match result {
+            Err(Error::Invalid { format, message }) => {
+                assert_eq!(format, FORMAT);
+                message
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_color_and_gray() {
+        let image = decode(b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06").unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 1, 3));
+        assert_eq!(image.data, ImageData::U8(vec![1, 2, 3, 4, 5, 6]));
+
+        let image = decode(b"P5 # a comment\n1 2 # another\n255\n\x07\x08").unwrap();
+        assert_eq!((image.width, image.height, image.channels), (1, 2, 1));
+        assert_eq!(image.data, ImageData::U8(vec![7, 8]));
+    }
+
+    #[test]
+    fn rescales_samples_to_full_range() {
+        let image = decode(b"P5 2 1 15\n\x00\x0f").unwrap();
+        assert_eq!(image.data, ImageData::U8(vec![0, 255]));
+
+        let image = decode(b"P5 2 1 65535\n\x12\x34\xff\xff").unwrap();
+        assert_eq!(image.data, ImageData::U16(vec![0x1234, 0xffff]));
+
+        let image = decode(b"P5 1 1 1000\n\x01\xf4").unwrap();
+        assert_eq!(image.data, ImageData::U16(vec![32768]));
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert_eq!(
+            invalid_message(decode(b"P")),
+            "not a binary PPM or PGM file"
+        );
+        assert_eq!(invalid_message(decode(b"P6 2 1")), "header is truncated");
+        assert_eq!(
+            invalid_message(decode(b"P6 2 1 255\n\x01\x02\x03")),
+            "file is truncated"
+        );
+        assert_eq!(
+            invalid_message(decode(b"P5 1 1 65535\n\x01")),
+            "file is truncated"
+        );
+    }
+
+    #[test]
+    fn rejects_huge_sizes_without_reserving() {
+        assert_eq!(
+            invalid_message(decode(b"P6 4294967295 4294967295 255\n")),
+            "size 4294967295x4294967295 is too large"
+        );
+        assert_eq!(
+            invalid_message(decode(b"P6 65536 65536 255\n\x00")),
+            "file is truncated"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert_eq!(
+            invalid_message(decode(b"P6 x 1 255\n")),
+            "header field is not a number"
+        );
+        assert_eq!(
+            invalid_message(decode(b"P6 99999999999 1 255\n")),
+            "header field is not a number"
+        );
+        assert_eq!(invalid_message(decode(b"P6 0 1 255\n")), "image is empty");
+        assert_eq!(
+            invalid_message(decode(b"P6 1 1 0\n\x00\x00\x00")),
+            "maximum value must be between 1 and 65535"
+        );
+        assert_eq!(
+            invalid_message(decode(b"P6 1 1 65536\n\x00\x00\x00")),
+            "maximum value must be between 1 and 65535"
+        );
+    }
+}
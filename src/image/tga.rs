@@ -0,0 +1,326 @@
+use crate::image::{sample_count, Error, Image, ImageData};
+
+const FORMAT: &str = "TGA";
+const HEADER_SIZE: usize = 18;
+
+/// TGA files have no magic number, so this checks that the header describes
+/// an image type and pixel depth this decoder understands.
+pub fn is_tga(bytes: &[u8]) -> bool {
+    if bytes.len() < HEADER_SIZE {
+        return false;
+    }
+    let color_map_type = bytes[1];
+    let image_type = bytes[2];
+    let depth = bytes[16];
+    color_map_type <= 1
+        && matches!(image_type, 1 | 2 | 3 | 9 | 10 | 11)
+        && matches!(depth, 8 | 15 | 16 | 24 | 32)
+}
+
+/// Decodes uncompressed and run-length encoded true-color, grayscale and
+/// color-mapped TGA images into 8-bit gray, RGB or RGBA.
+pub fn decode(bytes: &[u8]) -> Result<Image, Error> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(invalid("header is truncated"));
+    }
+    let id_length = bytes[0] as usize;
+    let color_map_type = bytes[1];
+    let image_type = bytes[2];
+    let color_map_first = u16::from_le_bytes([bytes[3], bytes[4]]) as usize;
+    let color_map_length = u16::from_le_bytes([bytes[5], bytes[6]]) as usize;
+    let color_map_depth = bytes[7];
+    let width = u32::from(u16::from_le_bytes([bytes[12], bytes[13]]));
+    let height = u32::from(u16::from_le_bytes([bytes[14], bytes[15]]));
+    let depth = bytes[16];
+    let descriptor = bytes[17];
+
+    if width == 0 || height == 0 {
+        return Err(invalid("image is empty"));
+    }
+    if descriptor & 0x10 != 0 {
+        return Err(Error::Unsupported {
+            format: FORMAT,
+            message: "right-to-left pixel order".into(),
+        });
+    }
+
+    let mut pos = HEADER_SIZE + id_length;
+    let color_map = if color_map_type == 1 {
+        let entry_size = (color_map_depth as usize).div_ceil(8);
+        let size = color_map_length * entry_size;
+        let entries = bytes
+            .get(pos..pos + size)
+            .ok_or_else(|| invalid("color map is truncated"))?;
+        pos += size;
+        let channels = pixel_channels(color_map_depth)?;
+        let mut color_map = Vec::with_capacity(color_map_length);
+        for entry in entries.chunks_exact(entry_size) {
+            color_map.push(convert_pixel(entry, color_map_depth, channels));
+        }
+        Some((color_map, channels))
+    } else {
+        None
+    };
+
+    let (channels, color_map) = match (image_type & !8, color_map) {
+        (1, Some((color_map, channels))) if depth == 8 => (channels, Some(color_map)),
+        (1, _) => return Err(invalid("color-mapped image without an 8-bit color map")),
+        (2, _) => (pixel_channels(depth)?, None),
+        (3, _) if depth == 8 => (1, None),
+        _ => return Err(invalid("unsupported image type or pixel depth")),
+    };
+    let pixel_size = (depth as usize).div_ceil(8);
+    let size = sample_count(FORMAT, width, height, pixel_size as u32)?;
+
+    // undo the run-length encoding first so both variants share the
+    // pixel conversion below
+    let raw = if image_type & 8 != 0 {
+        decode_rle(&bytes[pos.min(bytes.len())..], pixel_size, size)?
+    } else {
+        bytes
+            .get(pos..)
+            .and_then(|data| data.get(..size))
+            .ok_or_else(|| invalid("pixel data is truncated"))?
+            .to_vec()
+    };
+
+    // the raw pixels are all present by now, so the image size is backed by
+    // the file contents
+    let mut data = Vec::with_capacity(sample_count(FORMAT, width, height, channels)?);
+    for pixel in raw.chunks_exact(pixel_size) {
+        if let Some(ref color_map) = color_map {
+            let index = (pixel[0] as usize)
+                .checked_sub(color_map_first)
+                .filter(|&index| index < color_map.len())
+                .ok_or_else(|| invalid("color map index out of range"))?;
+            data.extend_from_slice(&color_map[index][..channels as usize]);
+        } else {
+            data.extend_from_slice(&convert_pixel(pixel, depth, channels)[..channels as usize]);
+        }
+    }
+
+    let mut image = Image {
+        width,
+        height,
+        channels,
+        data: ImageData::U8(data),
+    };
+    // rows are stored bottom row first unless the top-left origin bit is set
+    if descriptor & 0x20 == 0 {
+        image.flip_vertical();
+    }
+    Ok(image)
+}
+
+/// Expands run-length packets into `size` bytes of raw pixels.
+fn decode_rle(bytes: &[u8], pixel_size: usize, size: usize) -> Result<Vec<u8>, Error> {
+    let truncated = || invalid("run-length data is truncated");
+    // a header can claim far more pixels than the packets hold, so start from
+    // the encoded size and grow only as packets are read
+    let mut raw = Vec::with_capacity(size.min(bytes.len()));
+    let mut pos = 0;
+    while raw.len() < size {
+        let packet = *bytes.get(pos).ok_or_else(truncated)?;
+        pos += 1;
+        let count = (packet & 0x7f) as usize + 1;
+        if packet & 0x80 != 0 {
+            let pixel = bytes.get(pos..pos + pixel_size).ok_or_else(truncated)?;
+            pos += pixel_size;
+            for _ in 0..count {
+                raw.extend_from_slice(pixel);
+            }
+        } else {
+            let pixels = bytes
+                .get(pos..pos + count * pixel_size)
+                .ok_or_else(truncated)?;
+            pos += count * pixel_size;
+            raw.extend_from_slice(pixels);
+        }
+    }
+    // a packet may run past the last pixel
+    raw.truncate(size);
+    Ok(raw)
+}
+
+fn pixel_channels(depth: u8) -> Result<u32, Error> {
+    match depth {
+        8 => Ok(1),
+        15 | 16 | 24 => Ok(3),
+        32 => Ok(4),
+        _ => Err(invalid("unsupported pixel depth")),
+    }
+}
+
+/// Converts one little-endian BGR(A) pixel, or a 5-5-5 packed one, to RGBA.
+fn convert_pixel(pixel: &[u8], depth: u8, channels: u32) -> [u8; 4] {
+    match depth {
+        8 => [pixel[0], pixel[0], pixel[0], 255],
+        15 | 16 => {
+            let packed = u16::from_le_bytes([pixel[0], pixel[1]]);
+            let expand = |v: u16| ((v & 0x1f) * 255 / 31) as u8;
+            [
+                expand(packed >> 10),
+                expand(packed >> 5),
+                expand(packed),
+                255,
+            ]
+        }
+        _ if channels == 4 => [pixel[2], pixel[1], pixel[0], pixel[3]],
+        _ => [pixel[2], pixel[1], pixel[0], 255],
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Invalid {
+        format: FORMAT,
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(image_type: u8, width: u16, height: u16, depth: u8, descriptor: u8) -> Vec<u8> {
+        let mut header = vec![0; HEADER_SIZE];
+        header[2] = image_type;
+        header[12..14].copy_from_slice(&width.to_le_bytes());
+        header[14..16].copy_from_slice(&height.to_le_bytes());
+        header[16] = depth;
+        header[17] = descriptor;
+        header
+    }
+
+    fn invalid_message(result: Result<Image, Error>) -> String {
+        match result {
+            Err(Error::Invalid { format, message }) => {
+                assert_eq!(format, FORMAT);
+                message
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_bottom_up_true_color() {
+        let mut bytes = header(2, 2, 2, 24, 0);
+        // bottom row, then top row, in BGR order
+        bytes.extend_from_slice(&[3, 2, 1, 6, 5, 4, 9, 8, 7, 12, 11, 10]);
+        assert!(is_tga(&bytes));
+
+        let image = decode(&bytes).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 2, 3));
+        assert_eq!(
+            image.data,
+            ImageData::U8(vec![7, 8, 9, 10, 11, 12, 1, 2, 3, 4, 5, 6])
+        );
+    }
+
+    #[test]
+    fn decodes_run_length_encoded_alpha() {
+        let mut bytes = header(10, 3, 1, 32, 0x20);
+        // a run of two pixels, then a single raw one
+        bytes.extend_from_slice(&[0x81, 3, 2, 1, 4, 0x00, 7, 6, 5, 8]);
+
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.channels, 4);
+        assert_eq!(
+            image.data,
+            ImageData::U8(vec![1, 2, 3, 4, 1, 2, 3, 4, 5, 6, 7, 8])
+        );
+    }
+
+    #[test]
+    fn decodes_color_mapped_and_grayscale() {
+        let mut bytes = header(1, 2, 1, 8, 0x20);
+        bytes[1] = 1;
+        bytes[3..5].copy_from_slice(&1u16.to_le_bytes());
+        bytes[5..7].copy_from_slice(&2u16.to_le_bytes());
+        bytes[7] = 24;
+        bytes.extend_from_slice(&[3, 2, 1, 6, 5, 4]);
+        bytes.extend_from_slice(&[2, 1]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.data, ImageData::U8(vec![4, 5, 6, 1, 2, 3]));
+
+        let mut bytes = header(3, 2, 1, 8, 0x20);
+        bytes.extend_from_slice(&[10, 20]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.channels, 1);
+        assert_eq!(image.data, ImageData::U8(vec![10, 20]));
+    }
+
+    #[test]
+    fn expands_packed_pixels() {
+        let mut bytes = header(2, 1, 1, 16, 0x20);
+        bytes.extend_from_slice(&0x7c1fu16.to_le_bytes());
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.data, ImageData::U8(vec![255, 0, 255]));
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert_eq!(invalid_message(decode(&[0; 10])), "header is truncated");
+        assert!(!is_tga(&[0; 10]));
+
+        let mut bytes = header(2, 2, 2, 24, 0);
+        bytes.extend_from_slice(&[0; 11]);
+        assert_eq!(invalid_message(decode(&bytes)), "pixel data is truncated");
+
+        let mut bytes = header(10, 2, 2, 24, 0);
+        bytes.extend_from_slice(&[0x81, 0, 0]);
+        assert_eq!(
+            invalid_message(decode(&bytes)),
+            "run-length data is truncated"
+        );
+
+        let mut bytes = header(1, 1, 1, 8, 0);
+        bytes[1] = 1;
+        bytes[5..7].copy_from_slice(&4u16.to_le_bytes());
+        bytes[7] = 24;
+        bytes.extend_from_slice(&[0; 5]);
+        assert_eq!(invalid_message(decode(&bytes)), "color map is truncated");
+    }
+
+    #[test]
+    fn rejects_huge_images_without_pixel_data() {
+        let mut bytes = header(10, u16::MAX, u16::MAX, 32, 0);
+        bytes.extend_from_slice(&[0xff, 1, 2, 3, 4]);
+        assert_eq!(
+            invalid_message(decode(&bytes)),
+            "run-length data is truncated"
+        );
+
+        let bytes = header(2, u16::MAX, u16::MAX, 32, 0);
+        assert_eq!(invalid_message(decode(&bytes)), "pixel data is truncated");
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert_eq!(
+            invalid_message(decode(&header(2, 0, 1, 24, 0))),
+            "image is empty"
+        );
+        assert_eq!(
+            invalid_message(decode(&header(3, 1, 1, 24, 0))),
+            "unsupported image type or pixel depth"
+        );
+        assert_eq!(
+            invalid_message(decode(&header(1, 1, 1, 8, 0))),
+            "color-mapped image without an 8-bit color map"
+        );
+        assert!(matches!(
+            decode(&header(2, 1, 1, 24, 0x10)),
+            Err(Error::Unsupported { .. })
+        ));
+
+        let mut bytes = header(1, 1, 1, 8, 0);
+        bytes[1] = 1;
+        bytes[5..7].copy_from_slice(&1u16.to_le_bytes());
+        bytes[7] = 24;
+        bytes.extend_from_slice(&[0, 0, 0, 5]);
+        assert_eq!(
+            invalid_message(decode(&bytes)),
+            "color map index out of range"
+        );
+    }
+}
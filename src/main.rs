@@ -5,15 +5,17 @@ extern crate gl;
 extern crate sdl2;
 extern crate vec_2_10_10_10;
 
+pub mod assets;
+pub mod image;
 pub mod polys;
 pub mod render_gl;
 pub mod resources;
 
 use crate::polys::Vertex;
-use crate::render_gl::{buffer, data};
+use crate::render_gl::{buffer, data, VertexLayout};
 use crate::resources::Resources;
 use failure::err_msg;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 fn main() {
     if let Err(e) = run() {
@@ -22,7 +24,10 @@ fn main() {
 }
 
 fn run() -> Result<(), failure::Error> {
-    let res = Resources::from_relative_exe_path(Path::new("assets"))?;
+    let res = match assets_arg() {
+        Some(root) => Resources::from_root(&root)?,
+        None => Resources::locate(Path::new("assets"))?,
+    };
     let sdl = sdl2::init().map_err(err_msg)?;
     let sdl_video = sdl.video().unwrap();
 
@@ -50,8 +55,9 @@ fn run() -> Result<(), failure::Error> {
     let shader_program = render_gl::Program::from_shaders(&gl, &[vert_shader, frag_shader]).unwrap();
     */
     // shaders via res
-    let shader_program = render_gl::Program::from_res(&gl, &res, "shaders/triangle")?;
-    shader_program.set_used();
+    let mut shader_program = render_gl::ReloadableProgram::from_res(&gl, &res, "shaders/triangle")?;
+    render_gl::validate_vertex_layout::<Vertex>(shader_program.program())?;
+    shader_program.program().set_used();
 
     let vertices: Vec<Vertex> = vec![
         Vertex {
@@ -98,7 +104,8 @@ fn run() -> Result<(), failure::Error> {
             gl.Clear(gl::COLOR_BUFFER_BIT);
         }
 
-        shader_program.set_used();
+        shader_program.poll(&res);
+        shader_program.program().set_used();
         vao.bind();
         unsafe {
             gl.DrawArrays(gl::TRIANGLES, 0, 3);
@@ -109,6 +116,20 @@ fn run() -> Result<(), failure::Error> {
     Ok(())
 }
 
+/// The directory given with `--assets <dir>` or `--assets=<dir>`, if any.
+fn assets_arg() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--assets" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(root) = arg.to_str().and_then(|arg| arg.strip_prefix("--assets=")) {
+            return Some(root.into());
+        }
+    }
+    None
+}
+
 pub fn failure_to_string(e: failure::Error) -> String {
     use std::fmt::Write;
 
@@ -1,4 +1,4 @@
-use crate::render_gl::data;
+use crate::render_gl::{data, Attribute, VertexLayout};
 
 pub const VERT_RADIUS: f32 = 2.0;
 pub const CYLINDER_DIVS: i32 = 15;
@@ -96,20 +96,16 @@ pub struct Vertex {
     pub clr: data::u2_u10_u10_u10_rev_float,
 }
 
-impl Vertex {
-    pub fn vertex_attrib_pointers(gl: &gl::Gl) {
-        let stride = std::mem::size_of::<Self>();
-        let location = 0;
-        let offset = 0;
-        unsafe {
-            data::gl_vertex_f32::vertex_attrib_pointer(gl, stride, location, offset);
-        }
-
-        let location = 1;
-        let offset = offset + std::mem::size_of::<data::gl_vertex_f32>();
-        unsafe {
-            data::u2_u10_u10_u10_rev_float::vertex_attrib_pointer(gl, stride, location, offset);
-        }
+impl VertexLayout for Vertex {
+    fn attributes() -> Vec<Attribute> {
+        vec![
+            Attribute::new::<data::gl_vertex_f32>("pos", 0, 0),
+            Attribute::new::<data::u2_u10_u10_u10_rev_float>(
+                "clr",
+                1,
+                std::mem::size_of::<data::gl_vertex_f32>(),
+            ),
+        ]
     }
 }
 
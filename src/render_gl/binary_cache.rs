@@ -0,0 +1,134 @@
+use crate::render_gl::preprocess::Defines;
+use crate::render_gl::shader::{self, Error, Program};
+use crate::resources::Resources;
+use gl;
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Stores linked program binaries on disk so later runs can skip compiling.
+///
+/// Entries are keyed by the preprocessed sources and the driver's vendor,
+/// renderer and version strings; anything that changes one of those simply
+/// misses the cache and recompiles.
+pub struct ProgramBinaryCache {
+    dir: PathBuf,
+}
+
+impl ProgramBinaryCache {
+    pub fn new(dir: &Path) -> ProgramBinaryCache {
+        ProgramBinaryCache { dir: dir.into() }
+    }
+
+    pub fn load(&self, gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
+        self.load_with_defines(gl, res, name, &Defines::new())
+    }
+
+    pub fn load_with_defines(
+        &self,
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &Defines,
+    ) -> Result<Program, Error> {
+        let sources = shader::load_stage_sources(res, name, defines, false)?;
+        let stages = sources.iter().map(|s| s.stage).collect::<Vec<_>>();
+
+        let mut key = Fnv1a::new();
+        for driver_string in [gl::VENDOR, gl::RENDERER, gl::VERSION].iter() {
+            key.write(get_string(gl, *driver_string).as_bytes());
+        }
+        for source in sources.iter() {
+            key.write(source.stage.extension().as_bytes());
+            key.write(source.bytes());
+        }
+        let path = self.dir.join(format!("{:016x}.bin", key.finish()));
+
+        if let Some((format, binary)) = read_entry(&path) {
+            if let Some(program) = Program::from_binary(gl, format, &binary, stages) {
+                return Ok(program);
+            }
+        }
+
+        let program = Program::from_stage_sources(
+            gl,
+            name,
+            &sources,
+            &[(
+                gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                gl::TRUE as gl::types::GLint,
+            )],
+        )?;
+        if let Some((format, binary)) = program.binary() {
+            // the cache is best effort; failing to write it is not an error
+            let _ = write_entry(&self.dir, &path, format, &binary);
+        }
+        Ok(program)
+    }
+
+    /// Deletes every cached binary.
+    pub fn clear(&self) -> std::io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+// entry layout: binary format as a little-endian u32, then the binary
+fn read_entry(path: &Path) -> Option<(gl::types::GLenum, Vec<u8>)> {
+    let mut data = fs::read(path).ok()?;
+    if data.len() <= 4 {
+        return None;
+    }
+    let binary = data.split_off(4);
+    let format = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    Some((format, binary))
+}
+
+fn write_entry(
+    dir: &Path,
+    path: &Path,
+    format: gl::types::GLenum,
+    binary: &[u8],
+) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut data = Vec::with_capacity(binary.len() + 4);
+    data.extend_from_slice(&format.to_le_bytes());
+    data.extend_from_slice(binary);
+    fs::write(path, data)
+}
+
+fn get_string(gl: &gl::Gl, name: gl::types::GLenum) -> String {
+    let ptr = unsafe { gl.GetString(name) };
+    if ptr.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(ptr as *const _) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// 64-bit FNV-1a; unlike `DefaultHasher` its output is stable across builds,
+/// which matters for keys that outlive the process.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        // separate consecutive writes so ("ab", "c") != ("a", "bc")
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
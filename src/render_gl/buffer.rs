@@ -20,15 +20,11 @@ impl ArrayBuffer {
     }
 
     pub fn bind(&self) {
-        unsafe {
-            self.gl.BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-        }
+        self.gl.bind_buffer(gl::ARRAY_BUFFER, self.vbo);
     }
 
     pub fn unbind(&self) {
-        unsafe {
-            self.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
-        }
+        self.gl.bind_buffer(gl::ARRAY_BUFFER, 0);
     }
 
     pub fn static_draw_data<T>(&self, data: &[T]) {
@@ -45,6 +41,7 @@ impl ArrayBuffer {
 
 impl Drop for ArrayBuffer {
     fn drop(&mut self) {
+        self.gl.state().forget_buffer(self.vbo);
         unsafe {
             self.gl.DeleteBuffers(1, &mut self.vbo);
         }
@@ -70,20 +67,17 @@ impl VertexArray {
     }
 
     pub fn bind(&self) {
-        unsafe {
-            self.gl.BindVertexArray(self.vao);
-        }
+        self.gl.bind_vertex_array(self.vao);
     }
 
     pub fn unbind(&self) {
-        unsafe {
-            self.gl.BindVertexArray(0);
-        }
+        self.gl.bind_vertex_array(0);
     }
 }
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
+        self.gl.state().forget_vertex_array(self.vao);
         unsafe {
             self.gl.DeleteVertexArrays(1, &mut self.vao);
         }
@@ -0,0 +1,155 @@
+use crate::render_gl::shader::{self, Error, Program, Shader, ShaderStage};
+use crate::resources::Resources;
+use gl;
+use std::ops::{BitOr, BitOrAssign, Deref};
+
+/// Set of `glMemoryBarrier` bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryBarrier(pub gl::types::GLbitfield);
+
+impl MemoryBarrier {
+    pub const VERTEX_ATTRIB_ARRAY: MemoryBarrier =
+        MemoryBarrier(gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+    pub const ELEMENT_ARRAY: MemoryBarrier = MemoryBarrier(gl::ELEMENT_ARRAY_BARRIER_BIT);
+    pub const UNIFORM: MemoryBarrier = MemoryBarrier(gl::UNIFORM_BARRIER_BIT);
+    pub const TEXTURE_FETCH: MemoryBarrier = MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT);
+    pub const SHADER_IMAGE_ACCESS: MemoryBarrier =
+        MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+    pub const COMMAND: MemoryBarrier = MemoryBarrier(gl::COMMAND_BARRIER_BIT);
+    pub const PIXEL_BUFFER: MemoryBarrier = MemoryBarrier(gl::PIXEL_BUFFER_BARRIER_BIT);
+    pub const TEXTURE_UPDATE: MemoryBarrier = MemoryBarrier(gl::TEXTURE_UPDATE_BARRIER_BIT);
+    pub const BUFFER_UPDATE: MemoryBarrier = MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+    pub const FRAMEBUFFER: MemoryBarrier = MemoryBarrier(gl::FRAMEBUFFER_BARRIER_BIT);
+    pub const TRANSFORM_FEEDBACK: MemoryBarrier = MemoryBarrier(gl::TRANSFORM_FEEDBACK_BARRIER_BIT);
+    pub const ATOMIC_COUNTER: MemoryBarrier = MemoryBarrier(gl::ATOMIC_COUNTER_BARRIER_BIT);
+    pub const SHADER_STORAGE: MemoryBarrier = MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+    pub const CLIENT_MAPPED_BUFFER: MemoryBarrier =
+        MemoryBarrier(gl::CLIENT_MAPPED_BUFFER_BARRIER_BIT);
+    pub const QUERY_BUFFER: MemoryBarrier = MemoryBarrier(gl::QUERY_BUFFER_BARRIER_BIT);
+    pub const ALL: MemoryBarrier = MemoryBarrier(gl::ALL_BARRIER_BITS);
+}
+
+impl BitOr for MemoryBarrier {
+    type Output = MemoryBarrier;
+
+    fn bitor(self, other: MemoryBarrier) -> MemoryBarrier {
+        MemoryBarrier(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for MemoryBarrier {
+    fn bitor_assign(&mut self, other: MemoryBarrier) {
+        self.0 |= other.0;
+    }
+}
+
+pub fn memory_barrier(gl: &gl::Gl, barriers: MemoryBarrier) {
+    unsafe { gl.MemoryBarrier(barriers.0) }
+}
+
+/// A program made of a single compute stage.
+pub struct ComputeProgram {
+    gl: gl::Gl,
+    program: Program,
+    work_group_size: [u32; 3],
+    max_work_group_count: [u32; 3],
+}
+
+impl ComputeProgram {
+    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<ComputeProgram, Error> {
+        let program = Program::from_res(gl, res, name)?;
+        ComputeProgram::from_program(gl, name, program)
+    }
+
+    pub fn from_shader(gl: &gl::Gl, shader: Shader) -> Result<ComputeProgram, Error> {
+        let name = format!("<compute shader {}>", shader.id());
+        let program =
+            Program::from_shaders(gl, &[shader]).map_err(|log| shader::link_error(&name, &log))?;
+        ComputeProgram::from_program(gl, &name, program)
+    }
+
+    fn from_program(gl: &gl::Gl, name: &str, program: Program) -> Result<ComputeProgram, Error> {
+        if program.stages() != [ShaderStage::Compute] {
+            return Err(Error::InvalidStageCombination {
+                name: name.into(),
+                message: "a compute program must contain only a compute shader".into(),
+            });
+        }
+
+        let mut work_group_size: [gl::types::GLint; 3] = [0; 3];
+        let mut max_work_group_count: [gl::types::GLint; 3] = [0; 3];
+        unsafe {
+            gl.GetProgramiv(
+                program.id(),
+                gl::COMPUTE_WORK_GROUP_SIZE,
+                work_group_size.as_mut_ptr(),
+            );
+            for (axis, max) in max_work_group_count.iter_mut().enumerate() {
+                gl.GetIntegeri_v(
+                    gl::MAX_COMPUTE_WORK_GROUP_COUNT,
+                    axis as gl::types::GLuint,
+                    max,
+                );
+            }
+        }
+
+        Ok(ComputeProgram {
+            gl: gl.clone(),
+            program,
+            work_group_size: [
+                work_group_size[0] as u32,
+                work_group_size[1] as u32,
+                work_group_size[2] as u32,
+            ],
+            max_work_group_count: [
+                max_work_group_count[0] as u32,
+                max_work_group_count[1] as u32,
+                max_work_group_count[2] as u32,
+            ],
+        })
+    }
+
+    /// Local size declared by the shader's `layout(local_size_*)`.
+    pub fn work_group_size(&self) -> [u32; 3] {
+        self.work_group_size
+    }
+
+    pub fn max_work_group_count(&self) -> [u32; 3] {
+        self.max_work_group_count
+    }
+
+    pub fn dispatch(&self, x: u32, y: u32, z: u32) -> Result<(), Error> {
+        for (axis, (&count, &max)) in [x, y, z]
+            .iter()
+            .zip(self.max_work_group_count.iter())
+            .enumerate()
+        {
+            if count > max {
+                return Err(Error::WorkGroupCountTooLarge { axis, count, max });
+            }
+        }
+
+        self.program.set_used();
+        unsafe { self.gl.DispatchCompute(x, y, z) }
+        Ok(())
+    }
+
+    /// Dispatches with the group counts stored in `buffer` at `offset`
+    /// (three consecutive `uint`s).
+    pub fn dispatch_indirect(&self, buffer: gl::types::GLuint, offset: usize) {
+        self.program.set_used();
+        self.gl.bind_buffer(gl::DISPATCH_INDIRECT_BUFFER, buffer);
+        unsafe {
+            self.gl
+                .DispatchComputeIndirect(offset as gl::types::GLintptr)
+        }
+    }
+}
+
+impl Deref for ComputeProgram {
+    type Target = Program;
+
+    fn deref(&self) -> &Program {
+        &self.program
+    }
+}
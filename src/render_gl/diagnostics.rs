@@ -0,0 +1,352 @@
+use crate::render_gl::preprocess::Source;
+use std::fmt::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    fn parse(word: &str) -> Option<Severity> {
+        match word.trim().to_ascii_lowercase().as_str() {
+            "error" | "fatal error" => Some(Severity::Error),
+            "warning" => Some(Severity::Warning),
+            "info" | "note" => Some(Severity::Info),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One message from a shader or program info log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Source string number as used by `#line`.
+    pub source_id: Option<usize>,
+    /// Resource name the source id maps to, if it is known.
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.source_id) {
+            (Some(file), _) => write!(f, "{}:", file)?,
+            (None, Some(id)) => write!(f, "<source {}>:", id)?,
+            (None, None) => {}
+        }
+        if let Some(line) = self.line {
+            write!(f, "{}:", line)?;
+            if let Some(column) = self.column {
+                write!(f, "{}:", column)?;
+            }
+        }
+        if self.source_id.is_some() || self.file.is_some() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Parses an info log in the Mesa, NVIDIA or AMD format. Lines that match
+/// none of them are kept as location-less errors.
+pub fn parse_info_log(log: &str) -> Vec<Diagnostic> {
+    log.lines()
+        .map(|line| line.trim_end_matches('\0').trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            parse_mesa(line)
+                .or_else(|| parse_nvidia(line))
+                .or_else(|| parse_amd(line))
+                .or_else(|| parse_plain(line))
+                .unwrap_or_else(|| Diagnostic {
+                    severity: Severity::Error,
+                    source_id: None,
+                    file: None,
+                    line: None,
+                    column: None,
+                    message: line.into(),
+                })
+        })
+        .collect()
+}
+
+/// Fills in file names from the `#line` source ids of a preprocessed source.
+pub fn resolve_files(diagnostics: &mut [Diagnostic], source: &Source) {
+    for diagnostic in diagnostics.iter_mut() {
+        if let Some(id) = diagnostic.source_id {
+            diagnostic.file = source.file_name(id).map(|f| f.to_owned());
+        }
+    }
+}
+
+/// Renders diagnostics one per line, quoting the offending source line with
+/// a caret under the reported column when the source is available.
+pub fn render(diagnostics: &[Diagnostic], source: Option<&Source>) -> String {
+    let mut result = String::new();
+    for diagnostic in diagnostics {
+        let _ = writeln!(result, "{}", diagnostic);
+
+        let excerpt = match (source, diagnostic.source_id, diagnostic.line) {
+            (Some(source), Some(id), Some(line)) => source.line(id, line).map(|text| (line, text)),
+            _ => None,
+        };
+        if let Some((line, text)) = excerpt {
+            let gutter = line.to_string();
+            let column = diagnostic
+                .column
+                .map(|c| c.saturating_sub(1))
+                .unwrap_or_else(|| text.len() - text.trim_start().len());
+            let _ = writeln!(result, " {} | {}", gutter, text);
+            let _ = writeln!(
+                result,
+                " {} | {}^",
+                " ".repeat(gutter.len()),
+                " ".repeat(column)
+            );
+        }
+    }
+    result
+}
+
+// 0:12(5): error: `foo' undeclared
+fn parse_mesa(line: &str) -> Option<Diagnostic> {
+    let (location, rest) = line.split_once(": ")?;
+    let (source_id, position) = location.split_once(':')?;
+    let (line_number, column) = match position.split_once('(') {
+        Some((line_number, column)) => (line_number, Some(column.strip_suffix(')')?)),
+        None => (position, None),
+    };
+    let (severity, message) = rest.split_once(": ")?;
+
+    Some(Diagnostic {
+        severity: Severity::parse(severity)?,
+        source_id: Some(source_id.parse().ok()?),
+        file: None,
+        line: Some(line_number.parse().ok()?),
+        column: match column {
+            Some(column) => Some(column.parse().ok()?),
+            None => None,
+        },
+        message: message.into(),
+    })
+}
+
+// 0(12) : error C1008: undefined variable "foo"
+fn parse_nvidia(line: &str) -> Option<Diagnostic> {
+    let (location, rest) = line.split_once(" : ")?;
+    let (source_id, line_number) = location.split_once('(')?;
+    let line_number = line_number.strip_suffix(')')?;
+    let (severity, message) = rest.split_once(": ")?;
+    let severity = severity.split_whitespace().next()?;
+
+    Some(Diagnostic {
+        severity: Severity::parse(severity)?,
+        source_id: Some(source_id.parse().ok()?),
+        file: None,
+        line: Some(line_number.parse().ok()?),
+        column: None,
+        message: message.into(),
+    })
+}
+
+// ERROR: 0:12: 'foo' : undeclared identifier
+fn parse_amd(line: &str) -> Option<Diagnostic> {
+    let (severity, rest) = line.split_once(": ")?;
+    let severity = Severity::parse(severity)?;
+    let (source_id, rest) = rest.split_once(':')?;
+    let (line_number, message) = rest.split_once(':')?;
+
+    Some(Diagnostic {
+        severity,
+        source_id: Some(source_id.trim().parse().ok()?),
+        file: None,
+        line: Some(line_number.trim().parse().ok()?),
+        column: None,
+        message: message.trim().into(),
+    })
+}
+
+// error: vertex shader output `Color' not written by fragment shader
+fn parse_plain(line: &str) -> Option<Diagnostic> {
+    let (severity, message) = line.split_once(": ")?;
+
+    Some(Diagnostic {
+        severity: Severity::parse(severity)?,
+        source_id: None,
+        file: None,
+        line: None,
+        column: None,
+        message: message.trim().into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn diagnostic(
+        severity: Severity,
+        source_id: Option<usize>,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: &str,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity,
+            source_id,
+            file: None,
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn source() -> Source {
+        Source {
+            text: CString::new("").unwrap(),
+            files: vec!["main.frag".into(), "common.glsl".into()],
+            contents: vec![
+                "void main() {\n    colour = vec4(1.0);\n}\n".into(),
+                "float a;\n".into(),
+            ],
+        }
+    }
+
+    #[test]
+    fn parses_mesa_messages() {
+        assert_eq!(
+            parse_info_log("0:12(5): error: `foo' undeclared\n1:3: warning: unused"),
+            [
+                diagnostic(
+                    Severity::Error,
+                    Some(0),
+                    Some(12),
+                    Some(5),
+                    "`foo' undeclared"
+                ),
+                diagnostic(Severity::Warning, Some(1), Some(3), None, "unused"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_nvidia_messages() {
+        assert_eq!(
+            parse_info_log("0(12) : error C1008: undefined variable \"foo\""),
+            [diagnostic(
+                Severity::Error,
+                Some(0),
+                Some(12),
+                None,
+                "undefined variable \"foo\""
+            )]
+        );
+    }
+
+    #[test]
+    fn parses_amd_messages() {
+        assert_eq!(
+            parse_info_log("ERROR: 2:7: 'foo' : undeclared identifier"),
+            [diagnostic(
+                Severity::Error,
+                Some(2),
+                Some(7),
+                None,
+                "'foo' : undeclared identifier"
+            )]
+        );
+    }
+
+    #[test]
+    fn parses_messages_without_location() {
+        assert_eq!(
+            parse_info_log("error: vertex shader output `Color' not written"),
+            [diagnostic(
+                Severity::Error,
+                None,
+                None,
+                None,
+                "vertex shader output `Color' not written"
+            )]
+        );
+    }
+
+    #[test]
+    fn keeps_unrecognised_and_malformed_lines() {
+        let log = "Link failed.\n0:x(5): error: bad line\n0:12(5\n\n\0";
+        assert_eq!(
+            parse_info_log(log),
+            [
+                diagnostic(Severity::Error, None, None, None, "Link failed."),
+                diagnostic(Severity::Error, None, None, None, "0:x(5): error: bad line"),
+                diagnostic(Severity::Error, None, None, None, "0:12(5"),
+            ]
+        );
+        assert!(parse_info_log("").is_empty());
+    }
+
+    #[test]
+    fn unknown_severity_is_not_a_location() {
+        assert_eq!(
+            parse_info_log("0:1(1): remark: hello"),
+            [diagnostic(
+                Severity::Error,
+                None,
+                None,
+                None,
+                "0:1(1): remark: hello"
+            )]
+        );
+    }
+
+    #[test]
+    fn resolves_file_names_from_source_ids() {
+        let mut diagnostics = parse_info_log("1:1(1): error: a\n5:1(1): error: b");
+        resolve_files(&mut diagnostics, &source());
+
+        assert_eq!(diagnostics[0].file.as_deref(), Some("common.glsl"));
+        assert_eq!(diagnostics[1].file, None);
+        assert_eq!(diagnostics[0].to_string(), "common.glsl:1:1: error: a");
+        assert_eq!(diagnostics[1].to_string(), "<source 5>:1:1: error: b");
+    }
+
+    #[test]
+    fn renders_excerpt_with_caret() {
+        let source = source();
+        let mut diagnostics = parse_info_log("0:2(5): error: `colour' undeclared");
+        resolve_files(&mut diagnostics, &source);
+
+        assert_eq!(
+            render(&diagnostics, Some(&source)),
+            "main.frag:2:5: error: `colour' undeclared\n 2 |     colour = vec4(1.0);\n   |     ^\n"
+        );
+    }
+
+    #[test]
+    fn renders_without_excerpt_for_unknown_lines() {
+        let source = source();
+        let diagnostics = parse_info_log("0:99(1): error: past the end\nerror: plain");
+
+        assert_eq!(
+            render(&diagnostics, Some(&source)),
+            "<source 0>:99:1: error: past the end\nerror: plain\n"
+        );
+    }
+}
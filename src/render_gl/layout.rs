@@ -0,0 +1,248 @@
+use crate::render_gl::data;
+use crate::render_gl::shader::{Error, Program};
+use gl;
+use std::fmt::Write;
+
+/// How attribute data reaches the shader: converted to float
+/// (`VertexAttribPointer`), kept as integers (`VertexAttribIPointer`) or
+/// kept as doubles (`VertexAttribLPointer`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttribClass {
+    Float,
+    SignedInt,
+    UnsignedInt,
+    Double,
+}
+
+/// A type from `render_gl::data` that can back a vertex attribute.
+pub trait VertexAttribute {
+    const COMPONENTS: i32;
+    const CLASS: AttribClass;
+
+    /// # Safety
+    ///
+    /// A vertex array and an array buffer must be bound.
+    unsafe fn vertex_attrib_pointer(gl: &gl::Gl, stride: usize, location: usize, offset: usize);
+}
+
+macro_rules! impl_vertex_attribute {
+    ($($ty:ident => ($components:expr, $class:ident),)+) => {
+        $(
+            impl VertexAttribute for data::$ty {
+                const COMPONENTS: i32 = $components;
+                const CLASS: AttribClass = AttribClass::$class;
+
+                unsafe fn vertex_attrib_pointer(
+                    gl: &gl::Gl,
+                    stride: usize,
+                    location: usize,
+                    offset: usize,
+                ) {
+                    data::$ty::vertex_attrib_pointer(gl, stride, location, offset);
+                }
+            }
+        )+
+    };
+}
+
+impl_vertex_attribute! {
+    gl_vertex_f32 => (3, Float),
+    u2_u10_u10_u10_rev_float => (4, Float),
+    i8_ => (1, SignedInt),
+    i8_float => (1, Float),
+    i8_i8_i8 => (3, SignedInt),
+    i8_i8_i8_i8 => (4, SignedInt),
+    i8_i8_float => (2, Float),
+    i8_i8_i8_float => (3, Float),
+    i8_i8_i8_i8_float => (4, Float),
+    i16_ => (1, SignedInt),
+    i16_i16 => (2, SignedInt),
+    i16_i16_i16 => (3, SignedInt),
+    i16_i16_i16_i16 => (4, SignedInt),
+    i16_float => (1, Float),
+    i16_i16_float => (2, Float),
+    i16_i16_i16_float => (3, Float),
+    i16_i16_i16_i16_float => (4, Float),
+    i32_ => (1, SignedInt),
+    i32_i32 => (2, SignedInt),
+    i32_i32_i32 => (3, SignedInt),
+    i32_i32_i32_i32 => (4, SignedInt),
+    i32_float => (1, Float),
+    i32_i32_float => (2, Float),
+    i32_i32_i32_float => (3, Float),
+    i32_i32_i32_i32_float => (4, Float),
+    u8_ => (1, UnsignedInt),
+    u8_u8 => (2, UnsignedInt),
+    u8_u8_u8 => (3, UnsignedInt),
+    u8_u8_u8_u8 => (4, UnsignedInt),
+    u8_float => (1, Float),
+    u8_u8_float => (2, Float),
+    u8_u8_u8_float => (3, Float),
+    u8_u8_u8_u8_float => (4, Float),
+    u16_ => (1, UnsignedInt),
+    u16_u16 => (2, UnsignedInt),
+    u16_u16_u16 => (3, UnsignedInt),
+    u16_u16_u16_u16 => (4, UnsignedInt),
+    u16_float => (1, Float),
+    u16_u16_float => (2, Float),
+    u16_u16_u16_float => (3, Float),
+    u16_u16_u16_u16_float => (4, Float),
+    u32_ => (1, UnsignedInt),
+    u32_u32 => (2, UnsignedInt),
+    u32_u32_u32 => (3, UnsignedInt),
+    u32_u32_u32_u32 => (4, UnsignedInt),
+    u32_float => (1, Float),
+    u32_u32_float => (2, Float),
+    u32_u32_u32_float => (3, Float),
+    u32_u32_u32_u32_float => (4, Float),
+    f16_ => (1, Float),
+    f16_f16 => (2, Float),
+    f16_f16_f16 => (3, Float),
+    f16_f16_f16_f16 => (4, Float),
+    f32_ => (1, Float),
+    f32_f32 => (2, Float),
+    f32_f32_f32_f32 => (4, Float),
+    f64_ => (1, Double),
+    f64_f64 => (2, Double),
+    f64_f64_f64 => (3, Double),
+    f64_f64_f64_f64 => (4, Double),
+    i2_i10_i10_i10_rev => (4, Float),
+    u2_u10_u10_u10_rev => (4, Float),
+    u10_u11_u11_rev => (3, Float),
+    i2_i10_i10_i10_rev_float => (4, Float),
+    u10_u11_u11_rev_float => (3, Float),
+}
+
+/// One attribute of a vertex struct.
+#[derive(Copy, Clone)]
+pub struct Attribute {
+    pub name: &'static str,
+    pub location: u32,
+    pub offset: usize,
+    pub components: i32,
+    pub class: AttribClass,
+    setup: unsafe fn(&gl::Gl, usize, usize, usize),
+}
+
+impl Attribute {
+    pub fn new<T: VertexAttribute>(name: &'static str, location: u32, offset: usize) -> Attribute {
+        Attribute {
+            name,
+            location,
+            offset,
+            components: T::COMPONENTS,
+            class: T::CLASS,
+            setup: T::vertex_attrib_pointer,
+        }
+    }
+}
+
+impl Attribute {
+    /// GLSL type of the input that receives this attribute. Normalized
+    /// integer data arrives as floats, so it maps to `float`/`vec*`.
+    pub fn glsl_type(&self) -> String {
+        let (scalar, prefix) = match self.class {
+            AttribClass::Float => ("float", ""),
+            AttribClass::SignedInt => ("int", "i"),
+            AttribClass::UnsignedInt => ("uint", "u"),
+            AttribClass::Double => ("double", "d"),
+        };
+        if self.components == 1 {
+            scalar.into()
+        } else {
+            format!("{}vec{}", prefix, self.components)
+        }
+    }
+}
+
+/// A vertex struct described attribute by attribute, so the same description
+/// drives the attribute pointers and the checks against a program.
+pub trait VertexLayout: Sized {
+    fn attributes() -> Vec<Attribute>;
+
+    fn vertex_attrib_pointers(gl: &gl::Gl) {
+        let stride = std::mem::size_of::<Self>();
+        for attribute in Self::attributes() {
+            unsafe {
+                (attribute.setup)(gl, stride, attribute.location as usize, attribute.offset);
+            }
+        }
+    }
+}
+
+/// Generates the `layout(location = N) in <type> <name>;` block matching
+/// the layout of `V`.
+pub fn glsl_input_declarations<V: VertexLayout>() -> String {
+    let mut result = String::new();
+    for attribute in V::attributes() {
+        let _ = writeln!(
+            result,
+            "layout(location = {}) in {} {};",
+            attribute.location,
+            attribute.glsl_type(),
+            attribute.name
+        );
+    }
+    result
+}
+
+/// Compares the layout of `V` against the active inputs of `program` and
+/// lists every input whose attribute is missing or disagrees in component
+/// count or class.
+pub fn validate_vertex_layout<V: VertexLayout>(program: &Program) -> Result<(), Error> {
+    let attributes = V::attributes();
+    let mut message = String::new();
+
+    for input in program.interface().inputs {
+        let location = match input.location {
+            Some(location) => location,
+            None => continue, // built-in such as gl_VertexID
+        };
+        let attribute = match attributes.iter().find(|a| a.location == location) {
+            Some(attribute) => attribute,
+            None => {
+                let _ = writeln!(
+                    message,
+                    "input {} {} at location {} has no vertex attribute",
+                    input.ty, input.name, location
+                );
+                continue;
+            }
+        };
+        match input.ty.attrib_format() {
+            Some((components, class)) => {
+                if components != attribute.components {
+                    let _ = writeln!(
+                        message,
+                        "input {} {} at location {} has {} components but attribute {} has {}",
+                        input.ty,
+                        input.name,
+                        location,
+                        components,
+                        attribute.name,
+                        attribute.components
+                    );
+                }
+                if class != attribute.class {
+                    let _ = writeln!(
+                        message,
+                        "input {} {} at location {} expects {:?} data but attribute {} provides {:?}",
+                        input.ty, input.name, location, class, attribute.name, attribute.class
+                    );
+                }
+            }
+            None => {
+                let _ = writeln!(
+                    message,
+                    "input {} {} at location {} has a type that cannot be checked",
+                    input.ty, input.name, location
+                );
+            }
+        }
+    }
+
+    if !message.is_empty() {
+        return Err(Error::VertexLayoutMismatch { message });
+    }
+    Ok(())
+}
@@ -1,5 +1,35 @@
 pub mod data;
 pub mod buffer;
+mod binary_cache;
+mod compute;
+mod diagnostics;
+mod layout;
+mod permutation;
+mod pipeline;
+mod preprocess;
+mod reflect;
+mod reload;
+mod sampler;
 mod shader;
+mod texture;
+mod uniform;
 
-pub use self::shader::{Error, Program, Shader};
+pub use self::binary_cache::ProgramBinaryCache;
+pub use self::compute::{memory_barrier, ComputeProgram, MemoryBarrier};
+pub use self::diagnostics::{parse_info_log, Diagnostic, Severity};
+pub use self::layout::{
+    glsl_input_declarations, validate_vertex_layout, AttribClass, Attribute, VertexAttribute,
+    VertexLayout,
+};
+pub use self::permutation::ProgramCache;
+pub use self::pipeline::{ProgramPipeline, SeparableProgram};
+pub use self::preprocess::{preprocess, preprocess_with_defines, Defines, Source};
+pub use self::reflect::{Block, GlslType, ProgramInterface, Variable};
+pub use self::reload::ReloadableProgram;
+pub use self::sampler::{CompareFunc, Sampler, SamplerCache, SamplerDesc};
+pub use self::shader::{Error, Program, Shader, ShaderStage};
+pub(crate) use self::shader::{load_stage_sources, StageSource};
+pub use self::texture::{
+    mip_levels, Error as TextureError, Filter, Pixel, Texture2D, TextureFormat, Wrap,
+};
+pub use self::uniform::{TextureUnit, Uniform};
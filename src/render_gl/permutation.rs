@@ -0,0 +1,54 @@
+use crate::render_gl::preprocess::Defines;
+use crate::render_gl::shader::{Error, Program};
+use crate::resources::Resources;
+use gl;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Compiles each (resource name, define set) variant of a program once and
+/// hands out shared handles to it afterwards.
+pub struct ProgramCache {
+    gl: gl::Gl,
+    programs: RefCell<HashMap<(String, Defines), Rc<Program>>>,
+}
+
+impl ProgramCache {
+    pub fn new(gl: &gl::Gl) -> ProgramCache {
+        ProgramCache {
+            gl: gl.clone(),
+            programs: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(
+        &self,
+        res: &Resources,
+        name: &str,
+        defines: &Defines,
+    ) -> Result<Rc<Program>, Error> {
+        let key = (name.to_owned(), defines.clone());
+        if let Some(program) = self.programs.borrow().get(&key) {
+            return Ok(program.clone());
+        }
+
+        let program = Rc::new(Program::from_res_with_defines(
+            &self.gl, res, name, defines,
+        )?);
+        self.programs.borrow_mut().insert(key, program.clone());
+        Ok(program)
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.borrow().is_empty()
+    }
+
+    /// Drops the cache's handles; programs still referenced elsewhere stay alive.
+    pub fn clear(&self) {
+        self.programs.borrow_mut().clear();
+    }
+}
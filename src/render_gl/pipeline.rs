@@ -0,0 +1,218 @@
+use crate::render_gl::preprocess::Defines;
+use crate::render_gl::shader::{self, Error, Program, Shader, ShaderStage};
+use crate::resources::Resources;
+use gl;
+use std::fmt::Write;
+use std::ops::Deref;
+use std::rc::Rc;
+
+const SEPARABLE: [(gl::types::GLenum, gl::types::GLint); 1] =
+    [(gl::PROGRAM_SEPARABLE, gl::TRUE as gl::types::GLint)];
+
+/// A program linked with `PROGRAM_SEPARABLE`, so its stages can be combined
+/// with stages of other programs in a `ProgramPipeline`.
+pub struct SeparableProgram {
+    program: Program,
+}
+
+impl SeparableProgram {
+    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<SeparableProgram, Error> {
+        SeparableProgram::from_res_with_defines(gl, res, name, &Defines::new())
+    }
+
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &Defines,
+    ) -> Result<SeparableProgram, Error> {
+        let sources = shader::load_stage_sources(res, name, defines, true)?;
+        Ok(SeparableProgram {
+            program: Program::from_stage_sources(gl, name, &sources, &SEPARABLE)?,
+        })
+    }
+
+    pub fn from_shaders(gl: &gl::Gl, shaders: &[Shader]) -> Result<SeparableProgram, Error> {
+        let program = Program::link(gl, shaders, &SEPARABLE)
+            .map_err(|log| shader::link_error("<separable program>", &log))?;
+        Ok(SeparableProgram { program })
+    }
+}
+
+impl Deref for SeparableProgram {
+    type Target = Program;
+
+    fn deref(&self) -> &Program {
+        &self.program
+    }
+}
+
+/// Wrapper around a program pipeline object, combining stages from several
+/// separable programs without relinking them.
+pub struct ProgramPipeline {
+    gl: gl::Gl,
+    pipeline_id: gl::types::GLuint,
+    stages: Vec<(ShaderStage, Rc<SeparableProgram>)>,
+}
+
+impl ProgramPipeline {
+    pub fn new(gl: &gl::Gl) -> ProgramPipeline {
+        let mut pipeline_id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenProgramPipelines(1, &mut pipeline_id);
+        }
+        ProgramPipeline {
+            gl: gl.clone(),
+            pipeline_id,
+            stages: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.pipeline_id
+    }
+
+    /// Uses every stage of `program`.
+    pub fn use_program(&mut self, program: &Rc<SeparableProgram>) {
+        let stages = program.stages().to_vec();
+        self.use_stages(program, &stages);
+    }
+
+    /// Uses the given stages of `program`, replacing whatever program
+    /// previously provided them. Given stages that `program` lacks are left
+    /// empty, as GL does.
+    pub fn use_stages(&mut self, program: &Rc<SeparableProgram>, stages: &[ShaderStage]) {
+        let bits = stages.iter().fold(0, |bits, stage| bits | stage.gl_bit());
+        unsafe {
+            self.gl
+                .UseProgramStages(self.pipeline_id, bits, program.id());
+        }
+
+        self.stages.retain(|(stage, _)| !stages.contains(stage));
+        self.stages.extend(
+            stages
+                .iter()
+                .filter(|stage| program.stages().contains(stage))
+                .map(|&stage| (stage, program.clone())),
+        );
+        self.stages.sort_by_key(|(stage, _)| {
+            ShaderStage::ALL
+                .iter()
+                .position(|s| s == stage)
+                .unwrap_or(0)
+        });
+    }
+
+    /// Programs currently providing each stage, in pipeline order.
+    pub fn stages(&self) -> &[(ShaderStage, Rc<SeparableProgram>)] {
+        &self.stages
+    }
+
+    /// Checks that each stage's outputs match the inputs of the next stage
+    /// by location (or by name for variables without one) and type, then
+    /// runs the driver's own pipeline validation.
+    ///
+    /// Only boundaries between programs are compared, and only where the
+    /// producing stage is its program's last stage and the consuming stage
+    /// its program's first, since those are the interfaces a program reports.
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut message = String::new();
+        for pair in self.stages.windows(2) {
+            let (producer_stage, ref producer) = pair[0];
+            let (consumer_stage, ref consumer) = pair[1];
+            if Rc::ptr_eq(producer, consumer)
+                || producer.stages().last() != Some(&producer_stage)
+                || consumer.stages().first() != Some(&consumer_stage)
+            {
+                continue;
+            }
+
+            let outputs = producer.interface().outputs;
+            for input in consumer.interface().inputs {
+                if input.name.starts_with("gl_") {
+                    continue;
+                }
+                let output = outputs.iter().find(|output| match input.location {
+                    Some(location) => output.location == Some(location),
+                    None => output.name == input.name,
+                });
+                match output {
+                    None => {
+                        let _ = writeln!(
+                            message,
+                            "{} input {} is not written by the {} stage",
+                            consumer_stage, input.name, producer_stage
+                        );
+                    }
+                    Some(output) if output.ty != input.ty => {
+                        let _ = writeln!(
+                            message,
+                            "{} input {} is {} but the {} stage writes {} {}",
+                            consumer_stage,
+                            input.name,
+                            input.ty,
+                            producer_stage,
+                            output.ty,
+                            output.name
+                        );
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        if !message.is_empty() {
+            return Err(Error::InterfaceMismatch { message });
+        }
+
+        let mut success: gl::types::GLint = 0;
+        unsafe {
+            self.gl.ValidateProgramPipeline(self.pipeline_id);
+            self.gl
+                .GetProgramPipelineiv(self.pipeline_id, gl::VALIDATE_STATUS, &mut success);
+        }
+        if success == 0 {
+            return Err(shader::link_error("<program pipeline>", &self.info_log()));
+        }
+        Ok(())
+    }
+
+    /// Binds the pipeline. A program made current with `Program::set_used`
+    /// takes precedence over pipelines, so this also clears it.
+    pub fn bind(&self) {
+        self.gl.use_program(0);
+        self.gl.bind_program_pipeline(self.pipeline_id);
+    }
+
+    pub fn unbind(&self) {
+        self.gl.bind_program_pipeline(0);
+    }
+
+    fn info_log(&self) -> String {
+        let mut len: gl::types::GLint = 0;
+        unsafe {
+            self.gl
+                .GetProgramPipelineiv(self.pipeline_id, gl::INFO_LOG_LENGTH, &mut len);
+        }
+        let mut buffer: Vec<u8> = vec![0; len.max(1) as usize];
+        let mut written: gl::types::GLsizei = 0;
+        unsafe {
+            self.gl.GetProgramPipelineInfoLog(
+                self.pipeline_id,
+                buffer.len() as gl::types::GLsizei,
+                &mut written,
+                buffer.as_mut_ptr() as *mut gl::types::GLchar,
+            );
+        }
+        buffer.truncate(written as usize);
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+}
+
+impl Drop for ProgramPipeline {
+    fn drop(&mut self) {
+        self.gl.state().forget_program_pipeline(self.pipeline_id);
+        unsafe {
+            self.gl.DeleteProgramPipelines(1, &self.pipeline_id);
+        }
+    }
+}
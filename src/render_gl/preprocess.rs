@@ -0,0 +1,406 @@
+use crate::render_gl::shader::Error;
+use crate::resources::Resources;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fmt::Write;
+
+/// Preprocessor symbols injected right after the `#version` line.
+///
+/// Kept sorted so two sets with the same entries compare and hash equal.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Defines {
+    entries: BTreeMap<String, String>,
+}
+
+impl Defines {
+    pub fn new() -> Defines {
+        Defines::default()
+    }
+
+    /// Adds `#define <name>`.
+    pub fn with(self, name: &str) -> Defines {
+        self.with_value(name, "")
+    }
+
+    /// Adds `#define <name> <value>`.
+    pub fn with_value(mut self, name: &str, value: &str) -> Defines {
+        self.entries.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Shader source with every `#include` expanded.
+///
+/// `#line` directives refer to files by their index in `files`, so driver
+/// messages such as `0(12)` or `2:7` can be mapped back to a resource name.
+pub struct Source {
+    pub text: CString,
+    pub files: Vec<String>,
+    /// Original text of each entry in `files`, for quoting in diagnostics.
+    pub contents: Vec<String>,
+}
+
+impl Source {
+    pub fn file_name(&self, source_id: usize) -> Option<&str> {
+        self.files.get(source_id).map(|f| f.as_str())
+    }
+
+    /// Inserts `code` right after the `#version` line (or at the very start
+    /// if there is none). Line numbers of the original files are unaffected.
+    ///
+    /// Fails, leaving the source unchanged, if the text is not UTF-8 or
+    /// `code` contains a zero byte.
+    pub fn insert_after_version(&mut self, code: &str) -> Result<(), Error> {
+        let name = self.file_name(0).unwrap_or("<source>").to_owned();
+        let invalid = |message: &str| Error::InvalidSource {
+            name: name.clone(),
+            message: message.into(),
+        };
+
+        let mut text = self
+            .text
+            .to_str()
+            .map_err(|_| invalid("source is not valid UTF-8"))?
+            .to_owned();
+        let at = if text.starts_with("#version") {
+            text.find('\n').map(|i| i + 1).unwrap_or(text.len())
+        } else {
+            0
+        };
+        let mut code = code.to_owned();
+        if !code.is_empty() && !code.ends_with('\n') {
+            code.push('\n');
+        }
+        text.insert_str(at, &code);
+        self.text =
+            CString::new(text).map_err(|_| invalid("inserted code contains a zero byte"))?;
+        Ok(())
+    }
+
+    /// Returns a line of an original file, counting from 1.
+    pub fn line(&self, source_id: usize, line: usize) -> Option<&str> {
+        self.contents
+            .get(source_id)?
+            .lines()
+            .nth(line.checked_sub(1)?)
+    }
+}
+
+pub fn preprocess(res: &Resources, name: &str) -> Result<Source, Error> {
+    preprocess_with_defines(res, name, &Defines::new())
+}
+
+pub fn preprocess_with_defines(
+    res: &Resources,
+    name: &str,
+    defines: &Defines,
+) -> Result<Source, Error> {
+    let mut state = State {
+        res,
+        files: Vec::new(),
+        contents: Vec::new(),
+        stack: Vec::new(),
+        once: Vec::new(),
+        version: None,
+        body: String::new(),
+    };
+    state.include(name)?;
+
+    let mut text = state.version.unwrap_or_default();
+    for (define, value) in defines.iter() {
+        let _ = writeln!(text, "#define {} {}", define, value);
+    }
+    text.push_str(&state.body);
+
+    Ok(Source {
+        text: CString::new(text).map_err(|_| Error::InvalidInclude {
+            name: name.into(),
+            line: 0,
+            message: "source contains a zero byte".into(),
+        })?,
+        files: state.files,
+        contents: state.contents,
+    })
+}
+
+struct State<'a> {
+    res: &'a Resources,
+    files: Vec<String>,
+    contents: Vec<String>,
+    stack: Vec<String>,
+    once: Vec<String>,
+    version: Option<String>,
+    body: String,
+}
+
+impl<'a> State<'a> {
+    fn include(&mut self, name: &str) -> Result<(), Error> {
+        if self.stack.iter().any(|n| n == name) {
+            let mut chain = self.stack.clone();
+            chain.push(name.into());
+            return Err(Error::IncludeCycle {
+                chain: chain.join(" -> "),
+            });
+        }
+        if self.once.iter().any(|n| n == name) {
+            return Ok(());
+        }
+
+        let src = self
+            .res
+            .load_cstring(name)
+            .map_err(|e| Error::ResourceLoad {
+                name: name.into(),
+                inner: e,
+            })?;
+        let src = src.to_string_lossy().into_owned();
+
+        let file_id = match self.files.iter().position(|n| n == name) {
+            Some(id) => id,
+            None => {
+                self.files.push(name.into());
+                self.contents.push(src.clone());
+                self.files.len() - 1
+            }
+        };
+        let _ = writeln!(self.body, "#line 1 {}", file_id);
+
+        self.stack.push(name.into());
+        for (i, line) in src.lines().enumerate() {
+            let line_number = i + 1;
+            let directive = line.trim_start();
+
+            if directive.starts_with("#version") {
+                // hoisted to the top of the output; keep an empty line so the
+                // numbering of the rest of the file is unchanged
+                if self.version.is_none() {
+                    self.version = Some(format!("{}\n", directive.trim_end()));
+                }
+                self.body.push('\n');
+            } else if directive.strip_prefix("#pragma").map(str::trim) == Some("once") {
+                self.once.push(name.into());
+                self.body.push('\n');
+            } else if let Some(rest) = directive.strip_prefix("#include") {
+                let path = parse_include_path(rest).ok_or_else(|| Error::InvalidInclude {
+                    name: name.into(),
+                    line: line_number,
+                    message: format!("malformed directive `{}`", directive.trim_end()),
+                })?;
+                let target = resolve_include(name, path).ok_or_else(|| Error::InvalidInclude {
+                    name: name.into(),
+                    line: line_number,
+                    message: format!("include path `{}` leaves the resource root", path),
+                })?;
+                self.include(&target)?;
+                let _ = writeln!(self.body, "#line {} {}", line_number + 1, file_id);
+            } else {
+                self.body.push_str(line);
+                self.body.push('\n');
+            }
+        }
+        self.stack.pop();
+
+        Ok(())
+    }
+}
+
+fn parse_include_path(rest: &str) -> Option<&str> {
+    let rest = rest.trim();
+    let path = rest.strip_prefix('"')?.strip_suffix('"')?;
+    if path.is_empty() {
+        return None;
+    }
+    Some(path)
+}
+
+/// Resolves `path` relative to the directory of the including resource.
+/// A leading `/` makes the path relative to the resource root instead.
+fn resolve_include(including: &str, path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        let mut dir: Vec<&str> = including.split('/').collect();
+        dir.pop();
+        dir
+    };
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::{self, MemoryMount};
+
+    fn resources(files: &[(&str, &str)]) -> Resources {
+        let mut mount = MemoryMount::new();
+        for &(name, contents) in files {
+            mount.insert(name, contents.into()).unwrap();
+        }
+        let mut res = Resources::new();
+        res.mount(mount);
+        res
+    }
+
+    fn text(src: &Source) -> &str {
+        src.text.to_str().unwrap()
+    }
+
+    #[test]
+    fn expands_includes_with_line_directives() {
+        let res = resources(&[
+            (
+                "shaders/main.frag",
+                "#version 450\n#include \"common.glsl\"\nvoid main() {}\n",
+            ),
+            ("shaders/common.glsl", "float a;\n"),
+        ]);
+        let src = preprocess(&res, "shaders/main.frag").unwrap();
+
+        assert_eq!(
+            text(&src),
+            "#version 450\n#line 1 0\n\n#line 1 1\nfloat a;\n#line 3 0\nvoid main() {}\n"
+        );
+        assert_eq!(src.files, ["shaders/main.frag", "shaders/common.glsl"]);
+        assert_eq!(src.file_name(1), Some("shaders/common.glsl"));
+        assert_eq!(src.file_name(2), None);
+        assert_eq!(src.line(0, 3), Some("void main() {}"));
+        assert_eq!(src.line(0, 0), None);
+    }
+
+    #[test]
+    fn hoists_version_above_included_code() {
+        let res = resources(&[
+            ("main.vert", "#include \"a.glsl\"\nvoid main() {}\n"),
+            ("a.glsl", "#version 330 core  \nfloat a;\n"),
+        ]);
+        let src = preprocess(&res, "main.vert").unwrap();
+
+        assert!(text(&src).starts_with("#version 330 core\n#line 1 0\n"));
+        assert_eq!(text(&src).matches("#version").count(), 1);
+    }
+
+    #[test]
+    fn injects_defines_after_version() {
+        let res = resources(&[("main.vert", "#version 450\nvoid main() {}\n")]);
+        let defines = Defines::new().with("SHADOWS").with_value("LIGHTS", "4");
+        let src = preprocess_with_defines(&res, "main.vert", &defines).unwrap();
+
+        assert!(text(&src).starts_with("#version 450\n#define LIGHTS 4\n#define SHADOWS \n"));
+    }
+
+    #[test]
+    fn pragma_once_includes_a_file_once() {
+        let res = resources(&[
+            (
+                "main.vert",
+                "#include \"a.glsl\"\n#include \"a.glsl\"\nvoid main() {}\n",
+            ),
+            ("a.glsl", "#pragma once\nfloat a;\n"),
+        ]);
+        let src = preprocess(&res, "main.vert").unwrap();
+
+        assert_eq!(text(&src).matches("float a;").count(), 1);
+    }
+
+    #[test]
+    fn resolves_relative_and_root_paths() {
+        assert_eq!(
+            resolve_include("shaders/lit/main.frag", "../common.glsl").as_deref(),
+            Some("shaders/common.glsl")
+        );
+        assert_eq!(
+            resolve_include("shaders/lit/main.frag", "./brdf.glsl").as_deref(),
+            Some("shaders/lit/brdf.glsl")
+        );
+        assert_eq!(
+            resolve_include("shaders/lit/main.frag", "/lib/noise.glsl").as_deref(),
+            Some("lib/noise.glsl")
+        );
+        assert_eq!(resolve_include("main.frag", "../outside.glsl"), None);
+    }
+
+    #[test]
+    fn reports_include_cycles() {
+        let res = resources(&[
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "#include \"a.glsl\"\n"),
+        ]);
+        match preprocess(&res, "a.glsl") {
+            Err(Error::IncludeCycle { chain }) => assert_eq!(chain, "a.glsl -> b.glsl -> a.glsl"),
+            other => panic!("unexpected result {:?}", other.map(|src| src.files)),
+        }
+    }
+
+    #[test]
+    fn reports_missing_include_by_its_own_name() {
+        let res = resources(&[("main.vert", "#include \"missing.glsl\"\n")]);
+        match preprocess(&res, "main.vert") {
+            Err(Error::ResourceLoad {
+                name,
+                inner: resources::Error::NotFound { .. },
+            }) => assert_eq!(name, "missing.glsl"),
+            other => panic!("unexpected result {:?}", other.map(|src| src.files)),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_and_escaping_includes() {
+        let res = resources(&[
+            ("angle.vert", "void f();\n#include <common.glsl>\n"),
+            ("empty.vert", "#include \"\"\n"),
+            ("escape.vert", "#include \"../common.glsl\"\n"),
+        ]);
+        for &(name, expected_line) in &[("angle.vert", 2), ("empty.vert", 1), ("escape.vert", 1)] {
+            match preprocess(&res, name) {
+                Err(Error::InvalidInclude {
+                    name: file, line, ..
+                }) => {
+                    assert_eq!(file, name);
+                    assert_eq!(line, expected_line);
+                }
+                other => panic!("unexpected result {:?}", other.map(|src| src.files)),
+            }
+        }
+    }
+
+    #[test]
+    fn inserts_code_after_version() {
+        let res = resources(&[("main.vert", "#version 450\nvoid main() {}\n")]);
+        let mut src = preprocess(&res, "main.vert").unwrap();
+        src.insert_after_version("in vec3 Position;").unwrap();
+
+        assert!(text(&src).starts_with("#version 450\nin vec3 Position;\n#line 1 0\n"));
+    }
+
+    #[test]
+    fn rejects_inserted_zero_byte() {
+        let res = resources(&[("main.vert", "#version 450\nvoid main() {}\n")]);
+        let mut src = preprocess(&res, "main.vert").unwrap();
+        let before = src.text.clone();
+
+        match src.insert_after_version("in vec3\0 Position;") {
+            Err(Error::InvalidSource { name, .. }) => assert_eq!(name, "main.vert"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(src.text, before);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::render_gl::layout::AttribClass;
+use gl;
+use gl::types::{GLenum, GLint, GLuint};
+use std::fmt;
+
+/// GLSL type of an active variable, as reported by `GL_TYPE`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GlslType(pub GLenum);
+
+impl GlslType {
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match self.0 {
+            gl::FLOAT => "float",
+            gl::FLOAT_VEC2 => "vec2",
+            gl::FLOAT_VEC3 => "vec3",
+            gl::FLOAT_VEC4 => "vec4",
+            gl::DOUBLE => "double",
+            gl::DOUBLE_VEC2 => "dvec2",
+            gl::DOUBLE_VEC3 => "dvec3",
+            gl::DOUBLE_VEC4 => "dvec4",
+            gl::INT => "int",
+            gl::INT_VEC2 => "ivec2",
+            gl::INT_VEC3 => "ivec3",
+            gl::INT_VEC4 => "ivec4",
+            gl::UNSIGNED_INT => "uint",
+            gl::UNSIGNED_INT_VEC2 => "uvec2",
+            gl::UNSIGNED_INT_VEC3 => "uvec3",
+            gl::UNSIGNED_INT_VEC4 => "uvec4",
+            gl::BOOL => "bool",
+            gl::BOOL_VEC2 => "bvec2",
+            gl::BOOL_VEC3 => "bvec3",
+            gl::BOOL_VEC4 => "bvec4",
+            gl::FLOAT_MAT2 => "mat2",
+            gl::FLOAT_MAT3 => "mat3",
+            gl::FLOAT_MAT4 => "mat4",
+            gl::FLOAT_MAT2x3 => "mat2x3",
+            gl::FLOAT_MAT2x4 => "mat2x4",
+            gl::FLOAT_MAT3x2 => "mat3x2",
+            gl::FLOAT_MAT3x4 => "mat3x4",
+            gl::FLOAT_MAT4x2 => "mat4x2",
+            gl::FLOAT_MAT4x3 => "mat4x3",
+            gl::DOUBLE_MAT2 => "dmat2",
+            gl::DOUBLE_MAT3 => "dmat3",
+            gl::DOUBLE_MAT4 => "dmat4",
+            gl::SAMPLER_1D => "sampler1D",
+            gl::SAMPLER_2D => "sampler2D",
+            gl::SAMPLER_3D => "sampler3D",
+            gl::SAMPLER_CUBE => "samplerCube",
+            gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+            gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+            gl::SAMPLER_2D_ARRAY_SHADOW => "sampler2DArrayShadow",
+            gl::SAMPLER_CUBE_SHADOW => "samplerCubeShadow",
+            gl::SAMPLER_BUFFER => "samplerBuffer",
+            gl::SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+            gl::INT_SAMPLER_2D => "isampler2D",
+            gl::INT_SAMPLER_3D => "isampler3D",
+            gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+            gl::UNSIGNED_INT_SAMPLER_3D => "usampler3D",
+            gl::IMAGE_2D => "image2D",
+            gl::IMAGE_3D => "image3D",
+            gl::INT_IMAGE_2D => "iimage2D",
+            gl::UNSIGNED_INT_IMAGE_2D => "uimage2D",
+            gl::UNSIGNED_INT_ATOMIC_COUNTER => "atomic_uint",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+impl GlslType {
+    /// Component count and class a vertex attribute needs to feed an input
+    /// of this type, or `None` for types that are not plain scalars/vectors.
+    pub fn attrib_format(&self) -> Option<(i32, AttribClass)> {
+        let format = match self.0 {
+            gl::FLOAT => (1, AttribClass::Float),
+            gl::FLOAT_VEC2 => (2, AttribClass::Float),
+            gl::FLOAT_VEC3 => (3, AttribClass::Float),
+            gl::FLOAT_VEC4 => (4, AttribClass::Float),
+            gl::DOUBLE => (1, AttribClass::Double),
+            gl::DOUBLE_VEC2 => (2, AttribClass::Double),
+            gl::DOUBLE_VEC3 => (3, AttribClass::Double),
+            gl::DOUBLE_VEC4 => (4, AttribClass::Double),
+            gl::INT => (1, AttribClass::SignedInt),
+            gl::INT_VEC2 => (2, AttribClass::SignedInt),
+            gl::INT_VEC3 => (3, AttribClass::SignedInt),
+            gl::INT_VEC4 => (4, AttribClass::SignedInt),
+            gl::UNSIGNED_INT => (1, AttribClass::UnsignedInt),
+            gl::UNSIGNED_INT_VEC2 => (2, AttribClass::UnsignedInt),
+            gl::UNSIGNED_INT_VEC3 => (3, AttribClass::UnsignedInt),
+            gl::UNSIGNED_INT_VEC4 => (4, AttribClass::UnsignedInt),
+            _ => return None,
+        };
+        Some(format)
+    }
+}
+
+impl fmt::Display for GlslType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "<type 0x{:04x}>", self.0),
+        }
+    }
+}
+
+/// An active input, output or default-block uniform.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    /// `None` for built-ins such as `gl_VertexID`.
+    pub location: Option<GLuint>,
+    pub ty: GlslType,
+    pub array_size: GLint,
+}
+
+/// An active uniform or shader storage block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub name: String,
+    pub binding: GLuint,
+    pub data_size: GLint,
+    pub members: Vec<String>,
+}
+
+/// Everything a linked program reports about its interface.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramInterface {
+    pub inputs: Vec<Variable>,
+    pub outputs: Vec<Variable>,
+    pub uniforms: Vec<Variable>,
+    pub uniform_blocks: Vec<Block>,
+    pub storage_blocks: Vec<Block>,
+}
+
+impl ProgramInterface {
+    pub fn query(gl: &gl::Gl, program: GLuint) -> ProgramInterface {
+        unsafe {
+            ProgramInterface {
+                inputs: variables(gl, program, gl::PROGRAM_INPUT),
+                outputs: variables(gl, program, gl::PROGRAM_OUTPUT),
+                uniforms: variables(gl, program, gl::UNIFORM),
+                uniform_blocks: blocks(gl, program, gl::UNIFORM_BLOCK, gl::UNIFORM),
+                storage_blocks: blocks(
+                    gl,
+                    program,
+                    gl::SHADER_STORAGE_BLOCK,
+                    gl::BUFFER_VARIABLE,
+                ),
+            }
+        }
+    }
+
+    pub fn input(&self, name: &str) -> Option<&Variable> {
+        self.inputs.iter().find(|v| v.name == name)
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&Variable> {
+        self.uniforms.iter().find(|v| v.name == name)
+    }
+}
+
+impl fmt::Display for ProgramInterface {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sections = [
+            ("inputs", &self.inputs),
+            ("outputs", &self.outputs),
+            ("uniforms", &self.uniforms),
+        ];
+        for (title, vars) in sections.iter() {
+            writeln!(f, "{}:", title)?;
+            for var in vars.iter() {
+                match var.location {
+                    Some(location) => write!(f, "  layout(location = {}) ", location)?,
+                    None => write!(f, "  ")?,
+                }
+                write!(f, "{} {}", var.ty, var.name)?;
+                if var.array_size > 1 {
+                    write!(f, " [{}]", var.array_size)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        let blocks = [
+            ("uniform blocks", &self.uniform_blocks),
+            ("storage blocks", &self.storage_blocks),
+        ];
+        for (title, blocks) in blocks.iter() {
+            writeln!(f, "{}:", title)?;
+            for block in blocks.iter() {
+                writeln!(
+                    f,
+                    "  binding = {} {} ({} bytes): {}",
+                    block.binding,
+                    block.name,
+                    block.data_size,
+                    block.members.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+unsafe fn variables(gl: &gl::Gl, program: GLuint, interface: GLenum) -> Vec<Variable> {
+    const PROPS: [GLenum; 3] = [gl::TYPE, gl::ARRAY_SIZE, gl::LOCATION];
+
+    let mut result = Vec::new();
+    for index in 0..resource_count(gl, program, interface) {
+        // only the default block has locations; block members are listed
+        // with their block instead
+        if interface == gl::UNIFORM
+            && resource_props(gl, program, interface, index, &[gl::BLOCK_INDEX])[0] != -1
+        {
+            continue;
+        }
+        let values = resource_props(gl, program, interface, index, &PROPS);
+        result.push(Variable {
+            name: resource_name(gl, program, interface, index),
+            location: if values[2] < 0 {
+                None
+            } else {
+                Some(values[2] as GLuint)
+            },
+            ty: GlslType(values[0] as GLenum),
+            array_size: values[1],
+        });
+    }
+    result
+}
+
+unsafe fn blocks(
+    gl: &gl::Gl,
+    program: GLuint,
+    interface: GLenum,
+    member_interface: GLenum,
+) -> Vec<Block> {
+    const PROPS: [GLenum; 3] = [
+        gl::BUFFER_BINDING,
+        gl::BUFFER_DATA_SIZE,
+        gl::NUM_ACTIVE_VARIABLES,
+    ];
+
+    let mut result = Vec::new();
+    for index in 0..resource_count(gl, program, interface) {
+        let values = resource_props(gl, program, interface, index, &PROPS);
+
+        let mut member_indices = vec![0; values[2] as usize];
+        if !member_indices.is_empty() {
+            gl.GetProgramResourceiv(
+                program,
+                interface,
+                index,
+                1,
+                &gl::ACTIVE_VARIABLES,
+                member_indices.len() as gl::types::GLsizei,
+                std::ptr::null_mut(),
+                member_indices.as_mut_ptr(),
+            );
+        }
+
+        result.push(Block {
+            name: resource_name(gl, program, interface, index),
+            binding: values[0] as GLuint,
+            data_size: values[1],
+            members: member_indices
+                .iter()
+                .map(|&member| resource_name(gl, program, member_interface, member as GLuint))
+                .collect(),
+        });
+    }
+    result
+}
+
+unsafe fn resource_count(gl: &gl::Gl, program: GLuint, interface: GLenum) -> GLuint {
+    let mut count: GLint = 0;
+    gl.GetProgramInterfaceiv(program, interface, gl::ACTIVE_RESOURCES, &mut count);
+    count as GLuint
+}
+
+unsafe fn resource_props(
+    gl: &gl::Gl,
+    program: GLuint,
+    interface: GLenum,
+    index: GLuint,
+    props: &[GLenum],
+) -> Vec<GLint> {
+    let mut values = vec![0; props.len()];
+    gl.GetProgramResourceiv(
+        program,
+        interface,
+        index,
+        props.len() as gl::types::GLsizei,
+        props.as_ptr(),
+        values.len() as gl::types::GLsizei,
+        std::ptr::null_mut(),
+        values.as_mut_ptr(),
+    );
+    values
+}
+
+unsafe fn resource_name(gl: &gl::Gl, program: GLuint, interface: GLenum, index: GLuint) -> String {
+    let mut max_len: GLint = 0;
+    gl.GetProgramInterfaceiv(program, interface, gl::MAX_NAME_LENGTH, &mut max_len);
+
+    let mut buffer: Vec<u8> = vec![0; max_len.max(1) as usize];
+    let mut len: gl::types::GLsizei = 0;
+    gl.GetProgramResourceName(
+        program,
+        interface,
+        index,
+        buffer.len() as gl::types::GLsizei,
+        &mut len,
+        buffer.as_mut_ptr() as *mut gl::types::GLchar,
+    );
+    buffer.truncate(len as usize);
+    String::from_utf8_lossy(&buffer).into_owned()
+}
@@ -0,0 +1,112 @@
+use crate::render_gl::preprocess::Defines;
+use crate::render_gl::shader::{Error, Program};
+use crate::resources::Resources;
+use gl;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A program that recompiles itself when any of its source files change.
+///
+/// Sources are watched by polling their modification times from `poll`,
+/// which is cheap enough to call once per frame.
+pub struct ReloadableProgram {
+    gl: gl::Gl,
+    name: String,
+    defines: Defines,
+    program: Program,
+    files: Vec<(String, Option<SystemTime>)>,
+    poll_interval: Duration,
+    last_poll: Instant,
+}
+
+impl ReloadableProgram {
+    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<ReloadableProgram, Error> {
+        ReloadableProgram::from_res_with_defines(gl, res, name, &Defines::new())
+    }
+
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &Defines,
+    ) -> Result<ReloadableProgram, Error> {
+        let (program, files) = Program::from_res_tracked(gl, res, name, defines)?;
+        Ok(ReloadableProgram {
+            gl: gl.clone(),
+            name: name.into(),
+            defines: defines.clone(),
+            program,
+            files: modified_times(res, files),
+            poll_interval: Duration::from_millis(250),
+            last_poll: Instant::now(),
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Checks the watched files and rebuilds the program if one changed.
+    ///
+    /// Returns `true` if a new program was swapped in. On failure the error
+    /// is printed to stderr and the previous program is kept.
+    pub fn poll(&mut self, res: &Resources) -> bool {
+        if self.last_poll.elapsed() < self.poll_interval {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let changed = self
+            .files
+            .iter()
+            .any(|(name, modified)| res.modified(name).ok() != *modified);
+        if !changed {
+            return false;
+        }
+
+        match self.reload(res) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!(
+                    "Failed to reload program {}: {}",
+                    self.name,
+                    crate::failure_to_string(e.into())
+                );
+                false
+            }
+        }
+    }
+
+    /// Rebuilds the program unconditionally.
+    pub fn reload(&mut self, res: &Resources) -> Result<(), Error> {
+        // remember the new timestamps even on failure so a broken file is
+        // reported once, not on every poll
+        let result = Program::from_res_tracked(&self.gl, res, &self.name, &self.defines);
+        let files = match result {
+            Ok((program, files)) => {
+                self.program = program;
+                files
+            }
+            Err(e) => {
+                let names = self.files.iter().map(|(name, _)| name.clone()).collect();
+                self.files = modified_times(res, names);
+                return Err(e);
+            }
+        };
+        self.files = modified_times(res, files);
+        Ok(())
+    }
+}
+
+fn modified_times(res: &Resources, files: Vec<String>) -> Vec<(String, Option<SystemTime>)> {
+    files
+        .into_iter()
+        .map(|name| {
+            let modified = res.modified(&name).ok();
+            (name, modified)
+        })
+        .collect()
+}
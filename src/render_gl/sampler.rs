@@ -0,0 +1,276 @@
+use crate::render_gl::texture::{self, Filter, Wrap};
+use crate::render_gl::uniform::TextureUnit;
+use gl;
+use gl::types::{GLenum, GLint, GLuint};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// Depth comparison applied by shadow samplers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CompareFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl CompareFunc {
+    pub fn gl_enum(&self) -> GLenum {
+        match *self {
+            CompareFunc::Never => gl::NEVER,
+            CompareFunc::Less => gl::LESS,
+            CompareFunc::Equal => gl::EQUAL,
+            CompareFunc::LessEqual => gl::LEQUAL,
+            CompareFunc::Greater => gl::GREATER,
+            CompareFunc::NotEqual => gl::NOTEQUAL,
+            CompareFunc::GreaterEqual => gl::GEQUAL,
+            CompareFunc::Always => gl::ALWAYS,
+        }
+    }
+}
+
+/// Every setting of a sampler object. Built with the chained setters from
+/// `SamplerDesc::new()`, which starts from trilinear filtering and
+/// repeating wrap.
+///
+/// Floats are compared by bit pattern, so descriptions can key a hash map.
+#[derive(Copy, Clone, Debug)]
+pub struct SamplerDesc {
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    /// How levels are blended when minifying; `None` uses the base level only.
+    pub mipmap: Option<Filter>,
+    pub wrap: [Wrap; 3],
+    pub border_color: [f32; 4],
+    /// Turns on depth comparison, for sampling shadow maps.
+    pub compare: Option<CompareFunc>,
+    pub lod_bias: f32,
+    /// `1.0` turns anisotropic filtering off.
+    pub anisotropy: f32,
+}
+
+impl SamplerDesc {
+    pub fn new() -> SamplerDesc {
+        SamplerDesc {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            mipmap: Some(Filter::Linear),
+            wrap: [Wrap::Repeat; 3],
+            border_color: [0.0; 4],
+            compare: None,
+            lod_bias: 0.0,
+            anisotropy: 1.0,
+        }
+    }
+
+    pub fn filter(mut self, min: Filter, mag: Filter) -> SamplerDesc {
+        self.min_filter = min;
+        self.mag_filter = mag;
+        self
+    }
+
+    pub fn mipmap(mut self, mipmap: Option<Filter>) -> SamplerDesc {
+        self.mipmap = mipmap;
+        self
+    }
+
+    /// Wraps every axis the same way.
+    pub fn wrap(mut self, wrap: Wrap) -> SamplerDesc {
+        self.wrap = [wrap; 3];
+        self
+    }
+
+    pub fn wrap_s(mut self, wrap: Wrap) -> SamplerDesc {
+        self.wrap[0] = wrap;
+        self
+    }
+
+    pub fn wrap_t(mut self, wrap: Wrap) -> SamplerDesc {
+        self.wrap[1] = wrap;
+        self
+    }
+
+    pub fn wrap_r(mut self, wrap: Wrap) -> SamplerDesc {
+        self.wrap[2] = wrap;
+        self
+    }
+
+    pub fn border_color(mut self, color: [f32; 4]) -> SamplerDesc {
+        self.border_color = color;
+        self
+    }
+
+    pub fn compare(mut self, compare: Option<CompareFunc>) -> SamplerDesc {
+        self.compare = compare;
+        self
+    }
+
+    pub fn lod_bias(mut self, bias: f32) -> SamplerDesc {
+        self.lod_bias = bias;
+        self
+    }
+
+    pub fn anisotropy(mut self, anisotropy: f32) -> SamplerDesc {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn build(&self, gl: &gl::Gl) -> Sampler {
+        Sampler::new(gl, self)
+    }
+
+    fn key(&self) -> impl Eq + Hash {
+        (
+            self.min_filter,
+            self.mag_filter,
+            self.mipmap,
+            self.wrap,
+            self.border_color.map(f32::to_bits),
+            self.compare,
+            self.lod_bias.to_bits(),
+            self.anisotropy.to_bits(),
+        )
+    }
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc::new()
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &SamplerDesc) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// Filtering and wrap state kept apart from any texture. A sampler bound to
+/// a unit overrides the parameters of whatever texture is bound there.
+pub struct Sampler {
+    gl: gl::Gl,
+    sampler_id: GLuint,
+    desc: SamplerDesc,
+}
+
+impl Sampler {
+    pub fn new(gl: &gl::Gl, desc: &SamplerDesc) -> Sampler {
+        let mut sampler_id: GLuint = 0;
+        unsafe {
+            gl.GenSamplers(1, &mut sampler_id);
+        }
+
+        let parameteri = |name: GLenum, value: GLenum| unsafe {
+            gl.SamplerParameteri(sampler_id, name, value as GLint);
+        };
+        parameteri(
+            gl::TEXTURE_MIN_FILTER,
+            desc.min_filter.gl_min_enum(desc.mipmap),
+        );
+        parameteri(gl::TEXTURE_MAG_FILTER, desc.mag_filter.gl_enum());
+        parameteri(gl::TEXTURE_WRAP_S, desc.wrap[0].gl_enum());
+        parameteri(gl::TEXTURE_WRAP_T, desc.wrap[1].gl_enum());
+        parameteri(gl::TEXTURE_WRAP_R, desc.wrap[2].gl_enum());
+        match desc.compare {
+            Some(compare) => {
+                parameteri(gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE);
+                parameteri(gl::TEXTURE_COMPARE_FUNC, compare.gl_enum());
+            }
+            None => parameteri(gl::TEXTURE_COMPARE_MODE, gl::NONE),
+        }
+
+        let anisotropy = desc.anisotropy.max(1.0).min(texture::max_anisotropy(gl));
+        unsafe {
+            gl.SamplerParameterfv(
+                sampler_id,
+                gl::TEXTURE_BORDER_COLOR,
+                desc.border_color.as_ptr(),
+            );
+            gl.SamplerParameterf(sampler_id, gl::TEXTURE_LOD_BIAS, desc.lod_bias);
+            gl.SamplerParameterf(sampler_id, gl::TEXTURE_MAX_ANISOTROPY, anisotropy);
+        }
+
+        Sampler {
+            gl: gl.clone(),
+            sampler_id,
+            desc: *desc,
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.sampler_id
+    }
+
+    pub fn desc(&self) -> &SamplerDesc {
+        &self.desc
+    }
+
+    pub fn bind(&self, unit: TextureUnit) {
+        self.gl.bind_sampler(unit.0, self.sampler_id);
+    }
+
+    /// Lets the unit fall back to the bound texture's own parameters.
+    pub fn unbind(&self, unit: TextureUnit) {
+        self.gl.bind_sampler(unit.0, 0);
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.gl.state().forget_sampler(self.sampler_id);
+        unsafe {
+            self.gl.DeleteSamplers(1, &self.sampler_id);
+        }
+    }
+}
+
+/// Creates one sampler per distinct description and hands out shared
+/// handles to it afterwards.
+pub struct SamplerCache {
+    gl: gl::Gl,
+    samplers: RefCell<HashMap<SamplerDesc, Rc<Sampler>>>,
+}
+
+impl SamplerCache {
+    pub fn new(gl: &gl::Gl) -> SamplerCache {
+        SamplerCache {
+            gl: gl.clone(),
+            samplers: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, desc: &SamplerDesc) -> Rc<Sampler> {
+        self.samplers
+            .borrow_mut()
+            .entry(*desc)
+            .or_insert_with(|| Rc::new(Sampler::new(&self.gl, desc)))
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.borrow().is_empty()
+    }
+
+    /// Drops the cache's handles; samplers still referenced elsewhere stay alive.
+    pub fn clear(&self) {
+        self.samplers.borrow_mut().clear();
+    }
+}
@@ -1,6 +1,13 @@
+use crate::render_gl::diagnostics::{self, Diagnostic};
+use crate::render_gl::layout::{self, VertexLayout};
+use crate::render_gl::preprocess::{self, Defines, Source};
+use crate::render_gl::reflect::ProgramInterface;
+use crate::render_gl::uniform::Uniform;
 use crate::resources::{self, Resources};
 use gl;
 use std;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 
 #[derive(Debug, Fail)]
@@ -11,41 +18,204 @@ pub enum Error {
         #[cause]
         inner: resources::Error,
     },
-    #[fail(display = "Failed to link program {}: {}", name, message)]
-    LinkError { name: String, message: String },
-    #[fail(display = "Failed to complie shader {}: {}", name, message)]
-    CompileError { name: String, message: String },
+    #[fail(display = "Failed to link program {}:\n{}", name, message)]
+    LinkError {
+        name: String,
+        message: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    #[fail(display = "Failed to compile shader {}:\n{}", name, message)]
+    CompileError {
+        name: String,
+        message: String,
+        diagnostics: Vec<Diagnostic>,
+    },
     #[fail(display = "Cannot determine shader type for resource {}", name)]
     UnknownShaderType { name: String },
+    #[fail(display = "Program has no active uniform {}", name)]
+    UnknownUniform { name: String },
+    #[fail(display = "No shader stages found for program {}", name)]
+    NoShaderStages { name: String },
+    #[fail(display = "Invalid shader stages for program {}: {}", name, message)]
+    InvalidStageCombination { name: String, message: String },
+    #[fail(
+        display = "Work group count {} on axis {} exceeds the maximum of {}",
+        count, axis, max
+    )]
+    WorkGroupCountTooLarge { axis: usize, count: u32, max: u32 },
+    #[fail(display = "Include cycle: {}", chain)]
+    IncludeCycle { chain: String },
+    #[fail(display = "Invalid include in {} at line {}: {}", name, line, message)]
+    InvalidInclude {
+        name: String,
+        line: usize,
+        message: String,
+    },
+    #[fail(display = "Invalid shader source {}: {}", name, message)]
+    InvalidSource { name: String, message: String },
+    #[fail(display = "Invalid SPIR-V module {}: {}", name, message)]
+    InvalidSpirv { name: String, message: String },
+    #[fail(display = "Program pipeline stages do not match:\n{}", message)]
+    InterfaceMismatch { message: String },
+    #[fail(display = "Vertex layout does not match program inputs:\n{}", message)]
+    VertexLayoutMismatch { message: String },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    TessControl,
+    TessEvaluation,
+    Geometry,
+    Fragment,
+    Compute,
+}
+
+impl ShaderStage {
+    /// All stages in pipeline order.
+    pub const ALL: [ShaderStage; 6] = [
+        ShaderStage::Vertex,
+        ShaderStage::TessControl,
+        ShaderStage::TessEvaluation,
+        ShaderStage::Geometry,
+        ShaderStage::Fragment,
+        ShaderStage::Compute,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            ShaderStage::Vertex => ".vert",
+            ShaderStage::TessControl => ".tesc",
+            ShaderStage::TessEvaluation => ".tese",
+            ShaderStage::Geometry => ".geom",
+            ShaderStage::Fragment => ".frag",
+            ShaderStage::Compute => ".comp",
+        }
+    }
+
+    /// Bit used by `glUseProgramStages`.
+    pub fn gl_bit(&self) -> gl::types::GLbitfield {
+        match *self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER_BIT,
+            ShaderStage::TessControl => gl::TESS_CONTROL_SHADER_BIT,
+            ShaderStage::TessEvaluation => gl::TESS_EVALUATION_SHADER_BIT,
+            ShaderStage::Geometry => gl::GEOMETRY_SHADER_BIT,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER_BIT,
+            ShaderStage::Compute => gl::COMPUTE_SHADER_BIT,
+        }
+    }
+
+    pub fn gl_type(&self) -> gl::types::GLenum {
+        match *self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER,
+            ShaderStage::TessControl => gl::TESS_CONTROL_SHADER,
+            ShaderStage::TessEvaluation => gl::TESS_EVALUATION_SHADER,
+            ShaderStage::Geometry => gl::GEOMETRY_SHADER,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+            ShaderStage::Compute => gl::COMPUTE_SHADER,
+        }
+    }
+
+    pub fn from_gl_type(shader_type: gl::types::GLenum) -> Option<ShaderStage> {
+        ShaderStage::ALL
+            .iter()
+            .find(|stage| stage.gl_type() == shader_type)
+            .cloned()
+    }
+
+    pub fn from_resource_name(name: &str) -> Option<ShaderStage> {
+        ShaderStage::ALL
+            .iter()
+            .find(|stage| name.ends_with(stage.extension()))
+            .cloned()
+    }
+}
+
+impl std::fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match *self {
+            ShaderStage::Vertex => "vertex",
+            ShaderStage::TessControl => "tessellation control",
+            ShaderStage::TessEvaluation => "tessellation evaluation",
+            ShaderStage::Geometry => "geometry",
+            ShaderStage::Fragment => "fragment",
+            ShaderStage::Compute => "compute",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 pub struct Program {
     gl: gl::Gl,
     program_id: gl::types::GLuint,
+    uniform_locations: RefCell<HashMap<String, gl::types::GLint>>,
+    stages: Vec<ShaderStage>,
 }
 
 impl Program {
+    /// Builds a program from every `<name>.<stage>` resource that exists.
     pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
-        const POSSIBLE_EXT: [&str; 2] = [".vert", ".frag"];
+        Program::from_res_with_defines(gl, res, name, &Defines::new())
+    }
 
-        let resource_names = POSSIBLE_EXT
-            .iter()
-            .map(|file_extension| format!("{}{}", name, file_extension))
-            .collect::<Vec<String>>();
-        let shaders = resource_names
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &Defines,
+    ) -> Result<Program, Error> {
+        Program::from_res_tracked(gl, res, name, defines).map(|(program, _)| program)
+    }
+
+    /// Like `from_res_with_defines`, but also returns the name of every
+    /// resource that was read, including `#include`d files.
+    pub(crate) fn from_res_tracked(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &Defines,
+    ) -> Result<(Program, Vec<String>), Error> {
+        let sources = load_stage_sources(res, name, defines, false)?;
+        let program = Program::from_stage_sources(gl, name, &sources, &[])?;
+        let files = sources.iter().flat_map(|s| s.files()).collect();
+        Ok((program, files))
+    }
+
+    pub(crate) fn from_stage_sources(
+        gl: &gl::Gl,
+        name: &str,
+        sources: &[StageSource],
+        parameters: &[(gl::types::GLenum, gl::types::GLint)],
+    ) -> Result<Program, Error> {
+        let shaders = sources
             .iter()
-            .map(|resource_name| Shader::from_res(gl, res, resource_name))
+            .map(|s| s.compile(gl))
             .collect::<Result<Vec<Shader>, Error>>()?;
 
-        Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
-            name: name.into(),
-            message,
-        })
+        Program::link(gl, &shaders[..], parameters).map_err(|log| link_error(name, &log))
     }
 
     pub fn from_shaders(gl: &gl::Gl, shaders: &[Shader]) -> Result<Program, String> {
+        Program::link(gl, shaders, &[])
+    }
+
+    /// Links `shaders`, first applying `parameters` with `glProgramParameteri`.
+    pub(crate) fn link(
+        gl: &gl::Gl,
+        shaders: &[Shader],
+        parameters: &[(gl::types::GLenum, gl::types::GLint)],
+    ) -> Result<Program, String> {
+        let stages = shaders
+            .iter()
+            .map(|s| s.stage())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| "shader object has an unknown type".to_string())?;
+
         let program_id = unsafe { gl.CreateProgram() };
 
+        for &(parameter, value) in parameters {
+            unsafe { gl.ProgramParameteri(program_id, parameter, value) };
+        }
         for shader in shaders {
             unsafe { gl.AttachShader(program_id, shader.id()) };
         }
@@ -82,20 +252,119 @@ impl Program {
         Ok(Program {
             gl: gl.clone(),
             program_id,
+            uniform_locations: RefCell::new(HashMap::new()),
+            stages,
         })
     }
 
+    /// Loads a binary previously returned by `binary`. Returns `None` if the
+    /// driver rejects it, e.g. after a driver update.
+    pub(crate) fn from_binary(
+        gl: &gl::Gl,
+        format: gl::types::GLenum,
+        binary: &[u8],
+        stages: Vec<ShaderStage>,
+    ) -> Option<Program> {
+        let program_id = unsafe { gl.CreateProgram() };
+        let mut success: gl::types::GLint = 0;
+        unsafe {
+            gl.ProgramBinary(
+                program_id,
+                format,
+                binary.as_ptr() as *const gl::types::GLvoid,
+                binary.len() as gl::types::GLsizei,
+            );
+            gl.GetProgramiv(program_id, gl::LINK_STATUS, &mut success);
+        }
+
+        if success == 0 {
+            unsafe { gl.DeleteProgram(program_id) };
+            return None;
+        }
+        Some(Program {
+            gl: gl.clone(),
+            program_id,
+            uniform_locations: RefCell::new(HashMap::new()),
+            stages,
+        })
+    }
+
+    /// Returns the driver-specific binary format and contents of the linked
+    /// program, or `None` if the driver cannot provide one.
+    pub fn binary(&self) -> Option<(gl::types::GLenum, Vec<u8>)> {
+        let mut len: gl::types::GLint = 0;
+        unsafe {
+            self.gl
+                .GetProgramiv(self.program_id, gl::PROGRAM_BINARY_LENGTH, &mut len);
+        }
+        if len <= 0 {
+            return None;
+        }
+
+        let mut binary: Vec<u8> = vec![0; len as usize];
+        let mut format: gl::types::GLenum = 0;
+        let mut written: gl::types::GLsizei = 0;
+        unsafe {
+            self.gl.GetProgramBinary(
+                self.program_id,
+                len,
+                &mut written,
+                &mut format,
+                binary.as_mut_ptr() as *mut gl::types::GLvoid,
+            );
+        }
+        if written <= 0 {
+            return None;
+        }
+        binary.truncate(written as usize);
+        Some((format, binary))
+    }
+
     pub fn id(&self) -> gl::types::GLuint {
         self.program_id
     }
 
+    /// Stages that were linked into this program, in pipeline order.
+    pub fn stages(&self) -> &[ShaderStage] {
+        &self.stages
+    }
+
     pub fn set_used(&self) {
-        unsafe { self.gl.UseProgram(self.program_id) }
+        self.gl.use_program(self.program_id);
+    }
+
+    /// Queries the active inputs, outputs, uniforms and blocks of the program.
+    pub fn interface(&self) -> ProgramInterface {
+        ProgramInterface::query(&self.gl, self.program_id)
+    }
+
+    /// Looks up a uniform location, caching the result after the first query.
+    pub fn uniform_location(&self, name: &str) -> Result<gl::types::GLint, Error> {
+        if let Some(&location) = self.uniform_locations.borrow().get(name) {
+            return location_or_error(name, location);
+        }
+
+        let location = match CString::new(name) {
+            Ok(c_name) => unsafe { self.gl.GetUniformLocation(self.program_id, c_name.as_ptr()) },
+            Err(_) => -1,
+        };
+        self.uniform_locations
+            .borrow_mut()
+            .insert(name.to_owned(), location);
+
+        location_or_error(name, location)
+    }
+
+    pub fn set_uniform<T: Uniform + ?Sized>(&self, name: &str, value: &T) -> Result<(), Error> {
+        let location = self.uniform_location(name)?;
+        unsafe { value.set_uniform(&self.gl, self.program_id, location) };
+        Ok(())
     }
 }
 
 impl Drop for Program {
     fn drop(&mut self) {
+        self.gl.state().forget_program(self.program_id);
         unsafe { self.gl.DeleteProgram(self.program_id) }
     }
 }
@@ -107,22 +376,49 @@ pub struct Shader {
 
 impl Shader {
     pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Shader, Error> {
-        const POSSIBLE_EXT: [(&str, gl::types::GLenum); 2] =
-            [(".vert", gl::VERTEX_SHADER), (".frag", gl::FRAGMENT_SHADER)];
-        let shader_type = POSSIBLE_EXT
-            .iter()
-            .find(|&&(file_extension, _)| name.ends_with(file_extension))
-            .map(|&(_, s_type)| s_type)
+        Shader::from_res_with_defines(gl, res, name, &Defines::new())
+    }
+
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &Defines,
+    ) -> Result<Shader, Error> {
+        let stage = ShaderStage::from_resource_name(name)
             .ok_or_else(|| Error::UnknownShaderType { name: name.into() })?;
-        let src = res.load_cstring(name).map_err(|e| Error::ResourceLoad {
-            name: name.into(),
-            inner: e,
-        })?;
+        let src = preprocess::preprocess_with_defines(res, name, defines)?;
 
-        Shader::from_src(gl, &src, shader_type).map_err(|message| Error::CompileError {
-            name: name.into(),
-            message,
-        })
+        Shader::from_preprocessed(gl, name, stage, &src)
+    }
+
+    /// Like `from_res`, with the input declarations generated from `V`
+    /// inserted after the `#version` line.
+    pub fn from_res_with_vertex_layout<V: VertexLayout>(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+    ) -> Result<Shader, Error> {
+        let mut src = preprocess::preprocess(res, name)?;
+        src.insert_after_version(&layout::glsl_input_declarations::<V>())?;
+        Shader::from_source(gl, name, &src)
+    }
+
+    /// Compiles an already preprocessed source; `name` determines the stage.
+    pub fn from_source(gl: &gl::Gl, name: &str, src: &Source) -> Result<Shader, Error> {
+        let stage = ShaderStage::from_resource_name(name)
+            .ok_or_else(|| Error::UnknownShaderType { name: name.into() })?;
+        Shader::from_preprocessed(gl, name, stage, src)
+    }
+
+    fn from_preprocessed(
+        gl: &gl::Gl,
+        name: &str,
+        stage: ShaderStage,
+        src: &Source,
+    ) -> Result<Shader, Error> {
+        Shader::from_src(gl, &src.text, stage.gl_type())
+            .map_err(|log| compile_error(name, &log, Some(src)))
     }
 
     pub fn from_src(
@@ -137,6 +433,79 @@ impl Shader {
         })
     }
 
+    /// Creates a shader from a SPIR-V module, specializing `entry_point` with
+    /// the given `(constant id, value)` pairs. Values are the raw 32-bit
+    /// pattern of the constant, so floats are passed via `f32::to_bits`.
+    pub fn from_spirv(
+        gl: &gl::Gl,
+        bytes: &[u8],
+        stage: ShaderStage,
+        entry_point: &str,
+        constants: &[(u32, u32)],
+    ) -> Result<Shader, Error> {
+        let name = format!("<SPIR-V {} shader>", stage);
+        Shader::from_spirv_named(gl, &name, bytes, stage, entry_point, constants)
+    }
+
+    fn from_spirv_named(
+        gl: &gl::Gl,
+        name: &str,
+        bytes: &[u8],
+        stage: ShaderStage,
+        entry_point: &str,
+        constants: &[(u32, u32)],
+    ) -> Result<Shader, Error> {
+        const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+        let invalid = |message: &str| Error::InvalidSpirv {
+            name: name.into(),
+            message: message.into(),
+        };
+        if bytes.len() < 20 {
+            return Err(invalid("module is shorter than the 20-byte SPIR-V header"));
+        }
+        if !bytes.chunks_exact(4).remainder().is_empty() {
+            return Err(invalid("module size is not a whole number of words"));
+        }
+        if u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) != SPIRV_MAGIC {
+            return Err(invalid("missing SPIR-V magic number"));
+        }
+        let entry_point =
+            CString::new(entry_point).map_err(|_| invalid("entry point contains a zero byte"))?;
+
+        let indices = constants.iter().map(|c| c.0).collect::<Vec<_>>();
+        let values = constants.iter().map(|c| c.1).collect::<Vec<_>>();
+
+        let shader = Shader {
+            gl: gl.clone(),
+            shader_id: unsafe { gl.CreateShader(stage.gl_type()) },
+        };
+        let mut success: gl::types::GLint = 1;
+        unsafe {
+            gl.ShaderBinary(
+                1,
+                &shader.shader_id,
+                gl::SHADER_BINARY_FORMAT_SPIR_V,
+                bytes.as_ptr() as *const gl::types::GLvoid,
+                bytes.len() as gl::types::GLsizei,
+            );
+            gl.SpecializeShader(
+                shader.shader_id,
+                entry_point.as_ptr(),
+                constants.len() as gl::types::GLuint,
+                indices.as_ptr(),
+                values.as_ptr(),
+            );
+            gl.GetShaderiv(shader.shader_id, gl::COMPILE_STATUS, &mut success);
+        }
+        if success == 0 {
+            let log = shader_info_log(gl, shader.shader_id);
+            return Err(compile_error(name, &log, None));
+        }
+
+        Ok(shader)
+    }
+
     pub fn from_vert_src(gl: &gl::Gl, src: &CStr) -> Result<Shader, String> {
         Shader::from_src(gl, src, gl::VERTEX_SHADER)
     }
@@ -147,6 +516,17 @@ impl Shader {
     pub fn id(&self) -> gl::types::GLuint {
         self.shader_id
     }
+
+    /// The stage the driver reports for this shader, or `None` if it is not
+    /// one of the known stages.
+    pub fn stage(&self) -> Option<ShaderStage> {
+        let mut shader_type: gl::types::GLint = 0;
+        unsafe {
+            self.gl
+                .GetShaderiv(self.shader_id, gl::SHADER_TYPE, &mut shader_type);
+        }
+        ShaderStage::from_gl_type(shader_type as gl::types::GLenum)
+    }
 }
 
 impl Drop for Shader {
@@ -172,25 +552,197 @@ fn shader_from_src(
         gl.GetShaderiv(shader_id, gl::COMPILE_STATUS, &mut success);
     }
     if success == 0 {
-        let mut len: gl::types::GLint = 0;
-        unsafe {
-            gl.GetShaderiv(shader_id, gl::INFO_LOG_LENGTH, &mut len);
+        return Err(shader_info_log(gl, shader_id));
+    }
+
+    Ok(shader_id)
+}
+
+fn compile_error(name: &str, log: &str, source: Option<&Source>) -> Error {
+    let mut diagnostics = diagnostics::parse_info_log(log);
+    if let Some(source) = source {
+        diagnostics::resolve_files(&mut diagnostics, source);
+    }
+    Error::CompileError {
+        name: name.into(),
+        message: diagnostics::render(&diagnostics, source),
+        diagnostics,
+    }
+}
+
+pub(crate) fn link_error(name: &str, log: &str) -> Error {
+    let diagnostics = diagnostics::parse_info_log(log);
+    Error::LinkError {
+        name: name.into(),
+        message: diagnostics::render(&diagnostics, None),
+        diagnostics,
+    }
+}
+
+fn shader_info_log(gl: &gl::Gl, shader_id: gl::types::GLuint) -> String {
+    let mut len: gl::types::GLint = 0;
+    unsafe {
+        gl.GetShaderiv(shader_id, gl::INFO_LOG_LENGTH, &mut len);
+    }
+
+    let error: CString = create_whitespace_cstring_with_len(len as usize);
+
+    unsafe {
+        gl.GetShaderInfoLog(
+            shader_id,
+            len,
+            std::ptr::null_mut(),
+            error.as_ptr() as *mut gl::types::GLchar,
+        );
+    }
+    error.to_string_lossy().into_owned()
+}
+
+/// Code of one stage of a program loaded from resources.
+pub(crate) enum StageCode {
+    Glsl(Source),
+    Spirv(Vec<u8>),
+}
+
+pub(crate) struct StageSource {
+    pub name: String,
+    pub stage: ShaderStage,
+    pub code: StageCode,
+}
+
+impl StageSource {
+    /// Every resource the stage was built from, including `#include`s.
+    pub fn files(&self) -> Vec<String> {
+        match self.code {
+            StageCode::Glsl(ref source) => source.files.clone(),
+            StageCode::Spirv(_) => vec![self.name.clone()],
         }
+    }
 
-        let error: CString = create_whitespace_cstring_with_len(len as usize);
+    pub fn bytes(&self) -> &[u8] {
+        match self.code {
+            StageCode::Glsl(ref source) => source.text.as_bytes(),
+            StageCode::Spirv(ref bytes) => bytes,
+        }
+    }
 
-        unsafe {
-            gl.GetShaderInfoLog(
-                shader_id,
-                len,
-                std::ptr::null_mut(),
-                error.as_ptr() as *mut gl::types::GLchar,
-            );
+    fn compile(&self, gl: &gl::Gl) -> Result<Shader, Error> {
+        match self.code {
+            StageCode::Glsl(ref source) => {
+                Shader::from_preprocessed(gl, &self.name, self.stage, source)
+            }
+            StageCode::Spirv(ref bytes) => {
+                Shader::from_spirv_named(gl, &self.name, bytes, self.stage, "main", &[])
+            }
         }
-        return Err(error.to_string_lossy().into_owned());
     }
+}
 
-    Ok(shader_id)
+/// Loads every `<name>.<stage>` resource that exists and checks that the
+/// stages found can be linked together. A GLSL source takes precedence over
+/// a `<name>.<stage>.spv` module for the same stage.
+pub(crate) fn load_stage_sources(
+    res: &Resources,
+    name: &str,
+    defines: &Defines,
+    separable: bool,
+) -> Result<Vec<StageSource>, Error> {
+    let mut sources = Vec::new();
+    for stage in ShaderStage::ALL.iter() {
+        let resource_name = format!("{}{}", name, stage.extension());
+        let code = match preprocess::preprocess_with_defines(res, &resource_name, defines) {
+            Ok(source) => Some(StageCode::Glsl(source)),
+            // a missing stage file is skipped, a missing include is not
+            Err(Error::ResourceLoad {
+                name: ref missing,
+                ref inner,
+            }) if *missing == resource_name && is_missing(inner) => None,
+            Err(e) => return Err(e),
+        };
+        let (resource_name, code) = match code {
+            Some(code) => (resource_name, code),
+            None => {
+                let spirv_name = format!("{}.spv", resource_name);
+                match res.load_bytes(&spirv_name) {
+                    Ok(bytes) => (spirv_name, StageCode::Spirv(bytes)),
+                    Err(ref e) if is_missing(e) => continue,
+                    Err(e) => {
+                        return Err(Error::ResourceLoad {
+                            name: spirv_name,
+                            inner: e,
+                        })
+                    }
+                }
+            }
+        };
+        sources.push(StageSource {
+            name: resource_name,
+            stage: *stage,
+            code,
+        });
+    }
+
+    let stages = sources.iter().map(|s| s.stage).collect::<Vec<_>>();
+    validate_stages(name, &stages, separable)?;
+
+    let spirv_count = sources
+        .iter()
+        .filter(|s| match s.code {
+            StageCode::Spirv(_) => true,
+            StageCode::Glsl(_) => false,
+        })
+        .count();
+    if spirv_count != 0 && spirv_count != sources.len() {
+        return Err(Error::InvalidStageCombination {
+            name: name.into(),
+            message: "SPIR-V and GLSL stages cannot be linked into one program".into(),
+        });
+    }
+
+    Ok(sources)
+}
+
+fn is_missing(e: &resources::Error) -> bool {
+    matches!(*e, resources::Error::NotFound { .. })
+}
+
+/// Separable programs may hold any subset of the graphics stages, since the
+/// rest of the pipeline comes from other programs.
+fn validate_stages(name: &str, stages: &[ShaderStage], separable: bool) -> Result<(), Error> {
+    let invalid = |message: &str| {
+        Err(Error::InvalidStageCombination {
+            name: name.into(),
+            message: message.into(),
+        })
+    };
+
+    if stages.is_empty() {
+        return Err(Error::NoShaderStages { name: name.into() });
+    }
+    if stages.contains(&ShaderStage::Compute) {
+        if stages.len() > 1 {
+            return invalid("a compute shader cannot be linked with other stages");
+        }
+        return Ok(());
+    }
+    if separable {
+        return Ok(());
+    }
+    if !stages.contains(&ShaderStage::Vertex) {
+        return invalid("missing vertex shader");
+    }
+    if stages.contains(&ShaderStage::TessControl) && !stages.contains(&ShaderStage::TessEvaluation)
+    {
+        return invalid("tessellation control shader requires a tessellation evaluation shader");
+    }
+    Ok(())
+}
+
+fn location_or_error(name: &str, location: gl::types::GLint) -> Result<gl::types::GLint, Error> {
+    if location < 0 {
+        return Err(Error::UnknownUniform { name: name.into() });
+    }
+    Ok(location)
 }
 
 fn create_whitespace_cstring_with_len(len: usize) -> CString {
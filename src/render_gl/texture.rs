@@ -0,0 +1,551 @@
+use crate::image::{Image, ImageData};
+use crate::render_gl::data;
+use crate::render_gl::uniform::TextureUnit;
+use crate::resources::{self, Resources};
+use gl;
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+use half::f16;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Invalid texture upload: {}", message)]
+    InvalidTextureUpload { message: String },
+}
+
+/// Sized internal format of a texture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Rgba8,
+    /// 8-bit sRGB color, decoded to linear when sampled.
+    Srgb8,
+    Srgb8Alpha8,
+    R16,
+    Rg16,
+    Rgb16,
+    Rgba16,
+    R16f,
+    Rg16f,
+    Rgb16f,
+    Rgba16f,
+    R32f,
+    Rg32f,
+    Rgb32f,
+    Rgba32f,
+}
+
+impl TextureFormat {
+    pub fn gl_internal_format(&self) -> GLenum {
+        match *self {
+            TextureFormat::R8 => gl::R8,
+            TextureFormat::Rg8 => gl::RG8,
+            TextureFormat::Rgb8 => gl::RGB8,
+            TextureFormat::Rgba8 => gl::RGBA8,
+            TextureFormat::Srgb8 => gl::SRGB8,
+            TextureFormat::Srgb8Alpha8 => gl::SRGB8_ALPHA8,
+            TextureFormat::R16 => gl::R16,
+            TextureFormat::Rg16 => gl::RG16,
+            TextureFormat::Rgb16 => gl::RGB16,
+            TextureFormat::Rgba16 => gl::RGBA16,
+            TextureFormat::R16f => gl::R16F,
+            TextureFormat::Rg16f => gl::RG16F,
+            TextureFormat::Rgb16f => gl::RGB16F,
+            TextureFormat::Rgba16f => gl::RGBA16F,
+            TextureFormat::R32f => gl::R32F,
+            TextureFormat::Rg32f => gl::RG32F,
+            TextureFormat::Rgb32f => gl::RGB32F,
+            TextureFormat::Rgba32f => gl::RGBA32F,
+        }
+    }
+
+    pub fn components(&self) -> u32 {
+        match *self {
+            TextureFormat::R8 | TextureFormat::R16 | TextureFormat::R16f | TextureFormat::R32f => 1,
+            TextureFormat::Rg8
+            | TextureFormat::Rg16
+            | TextureFormat::Rg16f
+            | TextureFormat::Rg32f => 2,
+            TextureFormat::Rgb8
+            | TextureFormat::Srgb8
+            | TextureFormat::Rgb16
+            | TextureFormat::Rgb16f
+            | TextureFormat::Rgb32f => 3,
+            TextureFormat::Rgba8
+            | TextureFormat::Srgb8Alpha8
+            | TextureFormat::Rgba16
+            | TextureFormat::Rgba16f
+            | TextureFormat::Rgba32f => 4,
+        }
+    }
+
+    pub fn is_srgb(&self) -> bool {
+        matches!(*self, TextureFormat::Srgb8 | TextureFormat::Srgb8Alpha8)
+    }
+}
+
+/// A Rust type holding one pixel, uploaded with the matching pixel transfer
+/// format and type.
+///
+/// # Safety
+///
+/// The type must be laid out exactly as `FORMAT`'s components of `TYPE`,
+/// with no padding, since uploads read `size_of::<Self>()` bytes per pixel.
+pub unsafe trait Pixel: Copy {
+    const FORMAT: GLenum;
+    const TYPE: GLenum;
+}
+
+macro_rules! impl_pixel {
+    ($($ty:ty => ($format:ident, $type:ident),)+) => {
+        $(
+            unsafe impl Pixel for $ty {
+                const FORMAT: GLenum = gl::$format;
+                const TYPE: GLenum = gl::$type;
+            }
+        )+
+    };
+}
+
+impl_pixel! {
+    u8 => (RED, UNSIGNED_BYTE),
+    [u8; 1] => (RED, UNSIGNED_BYTE),
+    [u8; 2] => (RG, UNSIGNED_BYTE),
+    [u8; 3] => (RGB, UNSIGNED_BYTE),
+    [u8; 4] => (RGBA, UNSIGNED_BYTE),
+    u16 => (RED, UNSIGNED_SHORT),
+    [u16; 1] => (RED, UNSIGNED_SHORT),
+    [u16; 2] => (RG, UNSIGNED_SHORT),
+    [u16; 3] => (RGB, UNSIGNED_SHORT),
+    [u16; 4] => (RGBA, UNSIGNED_SHORT),
+    f16 => (RED, HALF_FLOAT),
+    [f16; 1] => (RED, HALF_FLOAT),
+    [f16; 2] => (RG, HALF_FLOAT),
+    [f16; 3] => (RGB, HALF_FLOAT),
+    [f16; 4] => (RGBA, HALF_FLOAT),
+    f32 => (RED, FLOAT),
+    [f32; 1] => (RED, FLOAT),
+    [f32; 2] => (RG, FLOAT),
+    [f32; 3] => (RGB, FLOAT),
+    [f32; 4] => (RGBA, FLOAT),
+    data::u8_ => (RED, UNSIGNED_BYTE),
+    data::u8_u8 => (RG, UNSIGNED_BYTE),
+    data::u8_u8_u8 => (RGB, UNSIGNED_BYTE),
+    data::u8_u8_u8_u8 => (RGBA, UNSIGNED_BYTE),
+    data::u16_ => (RED, UNSIGNED_SHORT),
+    data::u16_u16 => (RG, UNSIGNED_SHORT),
+    data::u16_u16_u16 => (RGB, UNSIGNED_SHORT),
+    data::u16_u16_u16_u16 => (RGBA, UNSIGNED_SHORT),
+    data::f16_ => (RED, HALF_FLOAT),
+    data::f16_f16 => (RG, HALF_FLOAT),
+    data::f16_f16_f16 => (RGB, HALF_FLOAT),
+    data::f16_f16_f16_f16 => (RGBA, HALF_FLOAT),
+    data::f32_ => (RED, FLOAT),
+    data::f32_f32 => (RG, FLOAT),
+    data::gl_vertex_f32 => (RGB, FLOAT),
+    data::f32_f32_f32_f32 => (RGBA, FLOAT),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl Wrap {
+    pub fn gl_enum(&self) -> GLenum {
+        match *self {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+            Wrap::ClampToBorder => gl::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+impl Filter {
+    pub fn gl_enum(&self) -> GLenum {
+        match *self {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+        }
+    }
+
+    /// Minification filter combining `self` within a level with `mipmap`
+    /// between levels; `None` samples only the base level.
+    pub fn gl_min_enum(&self, mipmap: Option<Filter>) -> GLenum {
+        match (*self, mipmap) {
+            (filter, None) => filter.gl_enum(),
+            (Filter::Nearest, Some(Filter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Nearest, Some(Filter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear, Some(Filter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (Filter::Linear, Some(Filter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+}
+
+/// Number of levels in a full mipmap chain for the given size.
+pub fn mip_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// A 2D texture with immutable storage: its format, size and level count
+/// are fixed when it is created, only the contents can change.
+pub struct Texture2D {
+    gl: gl::Gl,
+    texture_id: GLuint,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    levels: u32,
+}
+
+impl Texture2D {
+    pub fn new(
+        gl: &gl::Gl,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        levels: u32,
+    ) -> Result<Texture2D, Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidTextureUpload {
+                message: format!("size {}x{} is empty", width, height),
+            });
+        }
+        if levels == 0 || levels > mip_levels(width, height) {
+            return Err(Error::InvalidTextureUpload {
+                message: format!(
+                    "{} levels requested, a {}x{} texture has at most {}",
+                    levels,
+                    width,
+                    height,
+                    mip_levels(width, height)
+                ),
+            });
+        }
+
+        let mut texture_id: GLuint = 0;
+        unsafe {
+            gl.CreateTextures(gl::TEXTURE_2D, 1, &mut texture_id);
+            gl.TextureStorage2D(
+                texture_id,
+                levels as GLsizei,
+                format.gl_internal_format(),
+                width as GLsizei,
+                height as GLsizei,
+            );
+        }
+        let texture = Texture2D {
+            gl: gl.clone(),
+            texture_id,
+            format,
+            width,
+            height,
+            levels,
+        };
+        if levels > 1 {
+            texture.set_filter(Filter::Linear, Filter::Linear, Some(Filter::Linear));
+        } else {
+            // the default minification filter samples mipmaps, which would
+            // leave a single-level texture incomplete
+            texture.set_filter(Filter::Linear, Filter::Linear, None);
+        }
+        Ok(texture)
+    }
+
+    /// A texture with a full mipmap chain, its base level filled from
+    /// `pixels` and the rest generated.
+    pub fn from_pixels<P: Pixel>(
+        gl: &gl::Gl,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        pixels: &[P],
+    ) -> Result<Texture2D, Error> {
+        let texture = Texture2D::new(gl, format, width, height, mip_levels(width, height))?;
+        texture.upload(0, pixels)?;
+        texture.generate_mipmaps();
+        Ok(texture)
+    }
+
+    /// Decodes an image resource into a texture with a full mipmap chain.
+    /// 8-bit color images are assumed to hold sRGB values, as image files
+    /// normally do; use `from_image` to choose otherwise.
+    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Texture2D, Error> {
+        let mut image: Image = res.load(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+        image.flip_vertical();
+        Texture2D::from_image(gl, &image, true)
+    }
+
+    /// A texture with a full mipmap chain holding `image`, whose rows must
+    /// already be in GL order (bottom row first). `srgb` selects an sRGB
+    /// format for 8-bit RGB and RGBA images.
+    pub fn from_image(gl: &gl::Gl, image: &Image, srgb: bool) -> Result<Texture2D, Error> {
+        let (format, pixel_type, samples) = match image.data {
+            ImageData::U8(ref data) => {
+                let format = match (image.channels, srgb) {
+                    (1, _) => TextureFormat::R8,
+                    (2, _) => TextureFormat::Rg8,
+                    (3, false) => TextureFormat::Rgb8,
+                    (3, true) => TextureFormat::Srgb8,
+                    (_, false) => TextureFormat::Rgba8,
+                    (_, true) => TextureFormat::Srgb8Alpha8,
+                };
+                (format, gl::UNSIGNED_BYTE, data.len())
+            }
+            ImageData::U16(ref data) => {
+                let format = match image.channels {
+                    1 => TextureFormat::R16,
+                    2 => TextureFormat::Rg16,
+                    3 => TextureFormat::Rgb16,
+                    _ => TextureFormat::Rgba16,
+                };
+                (format, gl::UNSIGNED_SHORT, data.len())
+            }
+            ImageData::F32(ref data) => {
+                let format = match image.channels {
+                    1 => TextureFormat::R32f,
+                    2 => TextureFormat::Rg32f,
+                    3 => TextureFormat::Rgb32f,
+                    _ => TextureFormat::Rgba32f,
+                };
+                (format, gl::FLOAT, data.len())
+            }
+        };
+        let expected = image.width as usize * image.height as usize * image.channels as usize;
+        if samples != expected {
+            return Err(Error::InvalidTextureUpload {
+                message: format!("image has {} samples, {} expected", samples, expected),
+            });
+        }
+
+        let texture = Texture2D::new(
+            gl,
+            format,
+            image.width,
+            image.height,
+            mip_levels(image.width, image.height),
+        )?;
+        let pixels = match image.data {
+            ImageData::U8(ref data) => data.as_ptr() as *const gl::types::GLvoid,
+            ImageData::U16(ref data) => data.as_ptr() as *const gl::types::GLvoid,
+            ImageData::F32(ref data) => data.as_ptr() as *const gl::types::GLvoid,
+        };
+        let pixel_format = match image.channels {
+            1 => gl::RED,
+            2 => gl::RG,
+            3 => gl::RGB,
+            _ => gl::RGBA,
+        };
+        unsafe {
+            texture.sub_image(
+                0,
+                (0, 0, image.width, image.height),
+                pixel_format,
+                pixel_type,
+                pixels,
+            );
+        }
+        texture.generate_mipmaps();
+        Ok(texture)
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.texture_id
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+
+    /// Size of a mipmap level.
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// Replaces a whole mipmap level.
+    pub fn upload<P: Pixel>(&self, level: u32, pixels: &[P]) -> Result<(), Error> {
+        let (width, height) = self.level_size(level);
+        self.upload_region(level, 0, 0, width, height, pixels)
+    }
+
+    /// Replaces a rectangle of a mipmap level. Rows are tightly packed,
+    /// bottom row first.
+    pub fn upload_region<P: Pixel>(
+        &self,
+        level: u32,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pixels: &[P],
+    ) -> Result<(), Error> {
+        if level >= self.levels {
+            return Err(Error::InvalidTextureUpload {
+                message: format!("level {} of a {}-level texture", level, self.levels),
+            });
+        }
+        let (level_width, level_height) = self.level_size(level);
+        let fits = |offset: u32, size: u32, level_size: u32| {
+            offset
+                .checked_add(size)
+                .is_some_and(|end| end <= level_size)
+        };
+        if !fits(x, width, level_width) || !fits(y, height, level_height) {
+            return Err(Error::InvalidTextureUpload {
+                message: format!(
+                    "region {}x{} at ({}, {}) exceeds level {} size {}x{}",
+                    width, height, x, y, level, level_width, level_height
+                ),
+            });
+        }
+        let expected = width as usize * height as usize;
+        if pixels.len() != expected {
+            return Err(Error::InvalidTextureUpload {
+                message: format!("{} pixels given, {} expected", pixels.len(), expected),
+            });
+        }
+
+        unsafe {
+            self.sub_image(
+                level,
+                (x, y, width, height),
+                P::FORMAT,
+                P::TYPE,
+                pixels.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+        Ok(())
+    }
+
+    /// `pixels` must point to `width * height` tightly packed pixels of the
+    /// given format and type, and the region must lie within the level.
+    unsafe fn sub_image(
+        &self,
+        level: u32,
+        (x, y, width, height): (u32, u32, u32, u32),
+        format: GLenum,
+        pixel_type: GLenum,
+        pixels: *const gl::types::GLvoid,
+    ) {
+        // rows of odd-sized pixels are not 4-byte aligned
+        self.gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        self.gl.TextureSubImage2D(
+            self.texture_id,
+            level as GLint,
+            x as GLint,
+            y as GLint,
+            width as GLsizei,
+            height as GLsizei,
+            format,
+            pixel_type,
+            pixels,
+        );
+        self.gl.PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+    }
+
+    /// Fills every level below the base level from it.
+    pub fn generate_mipmaps(&self) {
+        unsafe { self.gl.GenerateTextureMipmap(self.texture_id) }
+    }
+
+    pub fn set_wrap(&self, s: Wrap, t: Wrap) {
+        unsafe {
+            self.gl
+                .TextureParameteri(self.texture_id, gl::TEXTURE_WRAP_S, s.gl_enum() as GLint);
+            self.gl
+                .TextureParameteri(self.texture_id, gl::TEXTURE_WRAP_T, t.gl_enum() as GLint);
+        }
+    }
+
+    pub fn set_border_color(&self, color: [f32; 4]) {
+        unsafe {
+            self.gl
+                .TextureParameterfv(self.texture_id, gl::TEXTURE_BORDER_COLOR, color.as_ptr());
+        }
+    }
+
+    /// Sets the minification and magnification filters; `mipmap` picks how
+    /// levels are blended when minifying, `None` samples only the base level.
+    pub fn set_filter(&self, min: Filter, mag: Filter, mipmap: Option<Filter>) {
+        unsafe {
+            self.gl.TextureParameteri(
+                self.texture_id,
+                gl::TEXTURE_MIN_FILTER,
+                min.gl_min_enum(mipmap) as GLint,
+            );
+            self.gl.TextureParameteri(
+                self.texture_id,
+                gl::TEXTURE_MAG_FILTER,
+                mag.gl_enum() as GLint,
+            );
+        }
+    }
+
+    /// Sets the maximum anisotropy, clamped to what the driver supports.
+    /// `1.0` turns anisotropic filtering off.
+    pub fn set_anisotropy(&self, anisotropy: f32) {
+        let anisotropy = anisotropy.max(1.0).min(max_anisotropy(&self.gl));
+        unsafe {
+            self.gl
+                .TextureParameterf(self.texture_id, gl::TEXTURE_MAX_ANISOTROPY, anisotropy);
+        }
+    }
+
+    pub fn bind(&self, unit: TextureUnit) {
+        self.gl
+            .bind_texture_unit(unit.0, gl::TEXTURE_2D, self.texture_id);
+    }
+
+    pub fn unbind(&self, unit: TextureUnit) {
+        self.gl.bind_texture_unit(unit.0, gl::TEXTURE_2D, 0);
+    }
+}
+
+impl Drop for Texture2D {
+    fn drop(&mut self) {
+        self.gl.state().forget_texture(self.texture_id);
+        unsafe {
+            self.gl.DeleteTextures(1, &self.texture_id);
+        }
+    }
+}
+
+pub(crate) fn max_anisotropy(gl: &gl::Gl) -> f32 {
+    let mut max: gl::types::GLfloat = 1.0;
+    unsafe {
+        gl.GetFloatv(gl::MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+    }
+    max
+}
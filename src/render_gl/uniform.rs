@@ -0,0 +1,129 @@
+use crate::render_gl::data;
+use gl;
+use gl::types::{GLint, GLuint};
+
+/// A value that can be written to a program uniform.
+///
+/// Values are written with the `ProgramUniform*` family, so the program does
+/// not need to be in use.
+pub trait Uniform {
+    /// # Safety
+    ///
+    /// `location` must be an active uniform of `program` with a GLSL type
+    /// matching `Self`.
+    unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint);
+}
+
+/// Texture unit index assigned to a `sampler*` uniform.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureUnit(pub u32);
+
+impl Uniform for TextureUnit {
+    unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+        gl.ProgramUniform1i(program, location, self.0 as GLint);
+    }
+}
+
+impl Uniform for bool {
+    unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+        gl.ProgramUniform1i(program, location, *self as GLint);
+    }
+}
+
+macro_rules! impl_scalar_uniform {
+    ($ty:ty, $func:ident, $array_func:ident) => {
+        impl Uniform for $ty {
+            unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+                gl.$func(program, location, *self);
+            }
+        }
+
+        impl Uniform for [$ty] {
+            unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+                gl.$array_func(program, location, self.len() as GLint, self.as_ptr());
+            }
+        }
+    };
+}
+
+impl_scalar_uniform!(f32, ProgramUniform1f, ProgramUniform1fv);
+impl_scalar_uniform!(f64, ProgramUniform1d, ProgramUniform1dv);
+impl_scalar_uniform!(i32, ProgramUniform1i, ProgramUniform1iv);
+impl_scalar_uniform!(u32, ProgramUniform1ui, ProgramUniform1uiv);
+
+// vector types from `data` are tightly packed, so a slice of them can be
+// handed to the `*v` setters as a flat array of components
+macro_rules! impl_vector_uniform {
+    ($ty:ty, $elem:ty, $func:ident, $array_func:ident, $($field:ident),+) => {
+        impl Uniform for $ty {
+            unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+                gl.$func(program, location, $(self.$field),+);
+            }
+        }
+
+        impl Uniform for [$ty] {
+            unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+                gl.$array_func(
+                    program,
+                    location,
+                    self.len() as GLint,
+                    self.as_ptr() as *const $elem,
+                );
+            }
+        }
+    };
+}
+
+impl_vector_uniform!(data::f32_, f32, ProgramUniform1f, ProgramUniform1fv, d0);
+impl_vector_uniform!(data::f32_f32, f32, ProgramUniform2f, ProgramUniform2fv, d0, d1);
+impl_vector_uniform!(data::gl_vertex_f32, f32, ProgramUniform3f, ProgramUniform3fv, d0, d1, d2);
+impl_vector_uniform!(data::f32_f32_f32_f32, f32, ProgramUniform4f, ProgramUniform4fv, d0, d1, d2, d3);
+impl_vector_uniform!(data::f64_, f64, ProgramUniform1d, ProgramUniform1dv, d0);
+impl_vector_uniform!(data::f64_f64, f64, ProgramUniform2d, ProgramUniform2dv, d0, d1);
+impl_vector_uniform!(data::f64_f64_f64, f64, ProgramUniform3d, ProgramUniform3dv, d0, d1, d2);
+impl_vector_uniform!(data::f64_f64_f64_f64, f64, ProgramUniform4d, ProgramUniform4dv, d0, d1, d2, d3);
+impl_vector_uniform!(data::i32_, i32, ProgramUniform1i, ProgramUniform1iv, d0);
+impl_vector_uniform!(data::i32_i32, i32, ProgramUniform2i, ProgramUniform2iv, d0, d1);
+impl_vector_uniform!(data::i32_i32_i32, i32, ProgramUniform3i, ProgramUniform3iv, d0, d1, d2);
+impl_vector_uniform!(data::i32_i32_i32_i32, i32, ProgramUniform4i, ProgramUniform4iv, d0, d1, d2, d3);
+impl_vector_uniform!(data::u32_, u32, ProgramUniform1ui, ProgramUniform1uiv, d0);
+impl_vector_uniform!(data::u32_u32, u32, ProgramUniform2ui, ProgramUniform2uiv, d0, d1);
+impl_vector_uniform!(data::u32_u32_u32, u32, ProgramUniform3ui, ProgramUniform3uiv, d0, d1, d2);
+impl_vector_uniform!(data::u32_u32_u32_u32, u32, ProgramUniform4ui, ProgramUniform4uiv, d0, d1, d2, d3);
+
+// matrices are arrays of columns (column-major, as GLSL expects)
+macro_rules! impl_matrix_uniform {
+    ($ty:ty, $elem:ty, $func:ident) => {
+        impl Uniform for $ty {
+            unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+                gl.$func(program, location, 1, gl::FALSE, self.as_ptr() as *const $elem);
+            }
+        }
+
+        impl Uniform for [$ty] {
+            unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+                gl.$func(
+                    program,
+                    location,
+                    self.len() as GLint,
+                    gl::FALSE,
+                    self.as_ptr() as *const $elem,
+                );
+            }
+        }
+    };
+}
+
+impl_matrix_uniform!([[f32; 2]; 2], f32, ProgramUniformMatrix2fv);
+impl_matrix_uniform!([[f32; 3]; 3], f32, ProgramUniformMatrix3fv);
+impl_matrix_uniform!([[f32; 4]; 4], f32, ProgramUniformMatrix4fv);
+impl_matrix_uniform!([[f64; 2]; 2], f64, ProgramUniformMatrix2dv);
+impl_matrix_uniform!([[f64; 3]; 3], f64, ProgramUniformMatrix3dv);
+impl_matrix_uniform!([[f64; 4]; 4], f64, ProgramUniformMatrix4dv);
+
+impl Uniform for [TextureUnit] {
+    unsafe fn set_uniform(&self, gl: &gl::Gl, program: GLuint, location: GLint) {
+        let units = self.iter().map(|unit| unit.0 as GLint).collect::<Vec<_>>();
+        gl.ProgramUniform1iv(program, location, units.len() as GLint, units.as_ptr());
+    }
+}
@@ -1,7 +1,22 @@
+use std::env;
 use std::ffi;
-use std::fs;
-use std::io::{self, Read};
+use std::fmt;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+mod mount;
+mod watch;
+
+#[cfg(feature = "embedded_assets")]
+pub use self::mount::EmbeddedMount;
+pub use self::mount::{DirMount, EntryKind, MemoryMount, Mount};
+pub use self::watch::{Change, ChangeKind, Subscription};
+
+#[cfg(feature = "embedded_assets")]
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+}
 
 #[derive(Debug, Fail)]
 pub enum Error {
@@ -11,50 +26,478 @@ pub enum Error {
     FileContainsNil,
     #[fail(display = "Failed to get executable path")]
     FailedToGetExePath,
+    #[fail(display = "Invalid resource name {}: {}", name, reason)]
+    InvalidResourceName { name: String, reason: &'static str },
+    #[fail(display = "Resource {} not found", name)]
+    NotFound { name: String },
+    #[fail(display = "Resource {} is not valid UTF-8", name)]
+    InvalidUtf8 { name: String },
+    #[fail(display = "Resource {} is not a directory", name)]
+    NotADirectory { name: String },
+    #[fail(display = "Failed to parse resource {}: {}", name, message)]
+    Parse { name: String, message: String },
+    #[fail(display = "Resource {} is not a file on disk", name)]
+    NotOnDisk { name: String },
+    #[fail(display = "Invalid asset pack {}: {}", path, message)]
+    InvalidPack { path: String, message: String },
+    #[fail(display = "Environment variable {} is not set", name)]
+    EnvVarNotSet { name: &'static str },
+    #[fail(display = "No resource directory found, tried:{}", tried)]
+    RootNotFound { tried: TriedPaths },
+}
+
+/// Directories searched for resources, one per line when displayed.
+#[derive(Debug)]
+pub struct TriedPaths(pub Vec<PathBuf>);
+
+impl fmt::Display for TriedPaths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for path in self.0.iter() {
+            write!(f, "\n    {}", path.display())?;
+        }
+        Ok(())
+    }
 }
 
+/// Names a resource directory that takes precedence over every other
+/// candidate in `Resources::locate`.
+pub const ASSETS_ENV_VAR: &str = "OPENGL_RUST_ASSETS";
+
 impl From<io::Error> for Error {
     fn from(other: io::Error) -> Self {
         Error::Io(other)
     }
 }
 
+/// A type that can be built from the raw contents of a resource.
+pub trait FromResource: Sized {
+    fn from_resource(name: &str, bytes: Vec<u8>) -> Result<Self, Error>;
+}
+
+impl FromResource for Vec<u8> {
+    fn from_resource(_name: &str, bytes: Vec<u8>) -> Result<Self, Error> {
+        Ok(bytes)
+    }
+}
+
+impl FromResource for String {
+    fn from_resource(name: &str, bytes: Vec<u8>) -> Result<Self, Error> {
+        String::from_utf8(bytes).map_err(|_| Error::InvalidUtf8 { name: name.into() })
+    }
+}
+
+impl FromResource for ffi::CString {
+    fn from_resource(_name: &str, bytes: Vec<u8>) -> Result<Self, Error> {
+        ffi::CString::new(bytes).map_err(|_| Error::FileContainsNil)
+    }
+}
+
+/// Resources are looked up through an ordered list of mounts; when several
+/// mounts provide the same name, the one mounted last wins.
 pub struct Resources {
-    root_path: PathBuf,
+    mounts: Vec<Box<dyn Mount>>,
 }
 
 impl Resources {
+    /// Resources with nothing mounted.
+    pub fn new() -> Resources {
+        Resources { mounts: Vec::new() }
+    }
+
+    /// Resources served from `root`, which must be a directory.
+    pub fn from_root(root: &Path) -> Result<Resources, Error> {
+        if !root.is_dir() {
+            return Err(Error::RootNotFound {
+                tried: TriedPaths(vec![root.into()]),
+            });
+        }
+        let mut res = Resources::new();
+        res.mount_dir(root);
+        Ok(res)
+    }
+
+    /// Resources served from the directory named by `OPENGL_RUST_ASSETS`.
+    pub fn from_env() -> Result<Resources, Error> {
+        let root = env::var_os(ASSETS_ENV_VAR).ok_or(Error::EnvVarNotSet {
+            name: ASSETS_ENV_VAR,
+        })?;
+        Resources::from_root(Path::new(&root))
+    }
+
+    /// Resources served from the first candidate root that exists. When
+    /// `OPENGL_RUST_ASSETS` is set it is the only candidate; otherwise
+    /// `rel_path` is looked for in the crate's source directory (debug builds
+    /// only, so edited shaders are reloaded without a rebuild), next to the
+    /// executable, then in the XDG data directories.
+    #[cfg(not(feature = "embedded_assets"))]
+    pub fn locate(rel_path: &Path) -> Result<Resources, Error> {
+        let candidates = Resources::candidate_roots(rel_path);
+        match candidates.iter().find(|root| root.is_dir()) {
+            Some(root) => Resources::from_root(root),
+            None => Err(Error::RootNotFound {
+                tried: TriedPaths(candidates),
+            }),
+        }
+    }
+
+    /// Like the disk-only `locate`, but a missing root is not an error: the
+    /// assets compiled into the binary are served, overridden by the root
+    /// if one is found.
+    ///
+    /// The directory next to the executable is not a candidate. build.rs
+    /// only copies assets there without this feature, so anything found
+    /// there is left over from an earlier build and would shadow the
+    /// embedded assets.
+    #[cfg(feature = "embedded_assets")]
+    pub fn locate(rel_path: &Path) -> Result<Resources, Error> {
+        let mut res = Resources::embedded();
+        let candidates = Resources::candidate_roots(rel_path);
+        if let Some(root) = candidates.iter().find(|root| root.is_dir()) {
+            res.mount_dir(root);
+        }
+        Ok(res)
+    }
+
+    /// Directories `locate` tries, in order.
+    pub fn candidate_roots(rel_path: &Path) -> Vec<PathBuf> {
+        if let Some(root) = env::var_os(ASSETS_ENV_VAR) {
+            return vec![root.into()];
+        }
+
+        let mut candidates = Vec::new();
+        if cfg!(debug_assertions) {
+            // ahead of the copy build.rs makes next to the executable
+            candidates.push(Path::new(env!("CARGO_MANIFEST_DIR")).join(rel_path));
+        }
+        #[cfg(not(feature = "embedded_assets"))]
+        if let Some(exe_path) = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|exe_path| exe_path.to_path_buf()))
+        {
+            candidates.push(exe_path.join(rel_path));
+        }
+        for data_dir in xdg_data_dirs() {
+            candidates.push(data_dir.join(env!("CARGO_PKG_NAME")).join(rel_path));
+        }
+        candidates
+    }
+
+    #[cfg(not(feature = "embedded_assets"))]
     pub fn from_relative_exe_path(rel_path: &Path) -> Result<Resources, Error> {
         let exe_file_name = ::std::env::current_exe().map_err(|_| Error::FailedToGetExePath)?;
         let exe_path = exe_file_name.parent().ok_or(Error::FailedToGetExePath)?;
-        Ok(Resources {
-            root_path: exe_path.join(rel_path),
-        })
+        let mut res = Resources::new();
+        res.mount_dir(&exe_path.join(rel_path));
+        Ok(res)
+    }
+
+    /// Serves only the assets compiled into the binary. As with `locate`,
+    /// the directory next to the executable is not mounted, since it can
+    /// only hold a stale copy; mount a root explicitly to override assets.
+    #[cfg(feature = "embedded_assets")]
+    pub fn from_relative_exe_path(_rel_path: &Path) -> Result<Resources, Error> {
+        Ok(Resources::embedded())
+    }
+
+    /// Only the assets compiled into the binary.
+    #[cfg(feature = "embedded_assets")]
+    pub fn embedded() -> Resources {
+        let mut res = Resources::new();
+        res.mount(EmbeddedMount::new(embedded::ASSETS));
+        res
     }
 
     pub fn from_exe_path() -> Result<Resources, Error> {
         Resources::from_relative_exe_path(Path::new(""))
     }
 
-    pub fn load_cstring(&self, resource_name: &str) -> Result<ffi::CString, Error> {
-        let mut file = fs::File::open(self.root_path.join(resource_name))?;
+    /// Mounts on top of everything mounted so far.
+    pub fn mount<M: Mount + 'static>(&mut self, mount: M) {
+        self.mounts.push(Box::new(mount));
+    }
+
+    pub fn mount_dir(&mut self, path: &Path) {
+        self.mount(DirMount::new(path));
+    }
+
+    /// Mounts a `.tar` or `.zip` asset pack, chosen by extension.
+    pub fn mount_pack(&mut self, path: &Path) -> Result<(), Error> {
+        let mount = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("tar") => MemoryMount::from_tar(path)?,
+            Some("zip") => MemoryMount::from_zip(path)?,
+            _ => {
+                return Err(Error::InvalidPack {
+                    path: path.display().to_string(),
+                    message: "expected a .tar or .zip file".into(),
+                })
+            }
+        };
+        self.mount(mount);
+        Ok(())
+    }
 
-        let mut buffer: Vec<u8> = Vec::with_capacity(file.metadata()?.len() as usize + 1);
-        file.read_to_end(&mut buffer)?;
+    pub fn load_cstring(&self, resource_name: &str) -> Result<ffi::CString, Error> {
+        let buffer = self.load_bytes(resource_name)?;
 
         if buffer.iter().find(|i| **i == 0).is_some() {
             return Err(Error::FileContainsNil);
         }
         Ok(unsafe { ffi::CString::from_vec_unchecked(buffer) })
     }
+
+    pub fn load_bytes(&self, resource_name: &str) -> Result<Vec<u8>, Error> {
+        let (mount, name) = self.find_file(resource_name)?;
+        mount
+            .read(&name)
+            .map_err(|e| not_found_or_io(resource_name, e))
+    }
+
+    pub fn load_string(&self, resource_name: &str) -> Result<String, Error> {
+        self.load(resource_name)
+    }
+
+    pub fn load<T: FromResource>(&self, resource_name: &str) -> Result<T, Error> {
+        T::from_resource(resource_name, self.load_bytes(resource_name)?)
+    }
+
+    pub fn exists(&self, resource_name: &str) -> bool {
+        self.find_file(resource_name).is_ok()
+    }
+
+    /// Lists the resource names of the entries of a directory across all
+    /// mounts, sorted. An empty name lists the resource root.
+    pub fn list_dir(&self, resource_name: &str) -> Result<Vec<String>, Error> {
+        let name = normalize_name(resource_name)?;
+
+        let mut found = false;
+        let mut names = Vec::new();
+        for mount in self.mounts.iter().rev() {
+            match mount.kind(&name) {
+                Some(EntryKind::Dir) => {}
+                // a file shadows directories of the same name further down
+                Some(EntryKind::File) if found => break,
+                Some(EntryKind::File) => {
+                    return Err(Error::NotADirectory {
+                        name: resource_name.into(),
+                    })
+                }
+                None => continue,
+            }
+            found = true;
+            for entry in mount.list_dir(&name)? {
+                if name.is_empty() {
+                    names.push(entry);
+                } else {
+                    names.push(format!("{}/{}", name, entry));
+                }
+            }
+        }
+        if !found {
+            return Err(Error::NotFound {
+                name: resource_name.into(),
+            });
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    pub fn modified(&self, resource_name: &str) -> Result<SystemTime, Error> {
+        let (mount, name) = self.find_file(resource_name)?;
+        mount
+            .modified(&name)
+            .map_err(|e| not_found_or_io(resource_name, e))
+    }
+
+    /// Watches a resource or resource directory for changes. The name does
+    /// not need to exist yet; creating it is reported as a change.
+    pub fn subscribe(&self, resource_name: &str) -> Result<Subscription, Error> {
+        Ok(Subscription::new(self, normalize_name(resource_name)?))
+    }
+
+    /// Path on disk of a resource, if the mount providing it is a directory.
+    pub fn resolve(&self, resource_name: &str) -> Result<PathBuf, Error> {
+        let (mount, name) = self.find_file(resource_name)?;
+        mount.path(&name).ok_or_else(|| Error::NotOnDisk {
+            name: resource_name.into(),
+        })
+    }
+
+    /// The topmost mount providing `resource_name` as a file, and the
+    /// normalized name to ask it for. Every access goes through here so no
+    /// name can reach outside a mount.
+    fn find_file(&self, resource_name: &str) -> Result<(&dyn Mount, String), Error> {
+        let name = normalize_name(resource_name)?;
+        if name.is_empty() {
+            return Err(Error::InvalidResourceName {
+                name: resource_name.into(),
+                reason: "name is empty",
+            });
+        }
+
+        for mount in self.mounts.iter().rev() {
+            match mount.kind(&name) {
+                Some(EntryKind::File) => return Ok((mount.as_ref(), name)),
+                // a directory shadows files of the same name further down
+                Some(EntryKind::Dir) => break,
+                None => {}
+            }
+        }
+        Err(Error::NotFound {
+            name: resource_name.into(),
+        })
+    }
 }
 
-fn resource_name_to_path(root_dir: &Path, location: &str) -> PathBuf {
-    let mut path: PathBuf = root_dir.into();
+impl Default for Resources {
+    fn default() -> Self {
+        Resources::new()
+    }
+}
 
-    for part in location.split("/") {
-        path = path.join(part);
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`, with the defaults from
+/// the XDG base directory specification for unset variables.
+#[cfg(unix)]
+fn xdg_data_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => dirs.push(PathBuf::from(dir)),
+        None => {
+            if let Some(home) = env::var_os("HOME") {
+                dirs.push(Path::new(&home).join(".local/share"));
+            }
+        }
+    }
+    match env::var_os("XDG_DATA_DIRS").filter(|dirs| !dirs.is_empty()) {
+        Some(data_dirs) => dirs.extend(env::split_paths(&data_dirs)),
+        None => {
+            dirs.push("/usr/local/share".into());
+            dirs.push("/usr/share".into());
+        }
     }
+    dirs
+}
+
+#[cfg(not(unix))]
+fn xdg_data_dirs() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+fn not_found_or_io(resource_name: &str, e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::NotFound => Error::NotFound {
+            name: resource_name.into(),
+        },
+        _ => Error::Io(e),
+    }
+}
+
+/// Resource names are `/`-separated on every platform. `.` components are
+/// skipped and `..` steps back up, but never above the root; absolute names,
+/// empty components and platform separators or prefixes (`\\`, `:`) are
+/// rejected. The empty name is the root itself.
+fn normalize_name(location: &str) -> Result<String, Error> {
+    let invalid = |reason| Error::InvalidResourceName {
+        name: location.into(),
+        reason,
+    };
 
-    path
+    if location.is_empty() {
+        return Ok(String::new());
+    }
+    if location.starts_with('/') {
+        return Err(invalid("absolute names are not allowed"));
+    }
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in location.split('/') {
+        match part {
+            "" => return Err(invalid("name contains an empty component")),
+            "." => {}
+            ".." => {
+                parts
+                    .pop()
+                    .ok_or_else(|| invalid("name escapes the resource root"))?;
+            }
+            part if part.contains('\\') || part.contains(':') => {
+                return Err(invalid("name contains a platform-specific separator"));
+            }
+            part => parts.push(part),
+        }
+    }
+
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(location: &str) -> &'static str {
+        match normalize_name(location) {
+            Err(Error::InvalidResourceName { reason, .. }) => reason,
+            other => panic!("{:?} was accepted as {:?}", location, other),
+        }
+    }
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize_name("").unwrap(), "");
+        assert_eq!(
+            normalize_name("shaders/triangle.vert").unwrap(),
+            "shaders/triangle.vert"
+        );
+        assert_eq!(
+            normalize_name("./shaders/./a.vert").unwrap(),
+            "shaders/a.vert"
+        );
+        assert_eq!(
+            normalize_name("shaders/lit/../a.vert").unwrap(),
+            "shaders/a.vert"
+        );
+        assert_eq!(normalize_name("shaders/..").unwrap(), "");
+    }
+
+    #[test]
+    fn rejects_names_outside_the_root() {
+        assert_eq!(reason(".."), "name escapes the resource root");
+        assert_eq!(
+            reason("shaders/../../a.vert"),
+            "name escapes the resource root"
+        );
+        assert_eq!(reason("/etc/passwd"), "absolute names are not allowed");
+    }
+
+    #[test]
+    fn rejects_malformed_names() {
+        assert_eq!(
+            reason("shaders//a.vert"),
+            "name contains an empty component"
+        );
+        assert_eq!(reason("shaders/"), "name contains an empty component");
+        assert_eq!(
+            reason("shaders\\a.vert"),
+            "name contains a platform-specific separator"
+        );
+        assert_eq!(
+            reason("C:/a.vert"),
+            "name contains a platform-specific separator"
+        );
+    }
+
+    #[test]
+    fn loads_reject_invalid_names() {
+        let mut res = Resources::new();
+        res.mount(MemoryMount::new().with("a.txt", b"a".to_vec()).unwrap());
+
+        assert!(matches!(
+            res.load_bytes("../a.txt"),
+            Err(Error::InvalidResourceName { .. })
+        ));
+        assert!(matches!(
+            res.load_bytes(""),
+            Err(Error::InvalidResourceName { .. })
+        ));
+        assert_eq!(res.load_bytes("./x/../a.txt").unwrap(), b"a");
+    }
 }
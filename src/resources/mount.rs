@@ -0,0 +1,572 @@
+use crate::resources::{normalize_name, Error};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+/// A source of resources mounted into `Resources`.
+///
+/// Names passed to a mount are already normalized: `/`-separated, relative,
+/// without `.` or `..` components. The empty name is the mount's root.
+pub trait Mount: Send + Sync {
+    /// Whether `name` exists in this mount, and what it is.
+    fn kind(&self, name: &str) -> Option<EntryKind>;
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>>;
+
+    fn modified(&self, name: &str) -> io::Result<SystemTime>;
+
+    /// File names (not full resource names) of the entries of a directory.
+    fn list_dir(&self, name: &str) -> io::Result<Vec<String>>;
+
+    /// Path on disk, for mounts backed by plain files.
+    fn path(&self, _name: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// A directory on disk.
+pub struct DirMount {
+    root_path: PathBuf,
+}
+
+impl DirMount {
+    pub fn new(root_path: &Path) -> DirMount {
+        DirMount {
+            root_path: root_path.into(),
+        }
+    }
+
+    fn to_path(&self, name: &str) -> PathBuf {
+        let mut path = self.root_path.clone();
+        for part in name.split('/').filter(|part| !part.is_empty()) {
+            path = path.join(part);
+        }
+        path
+    }
+}
+
+impl Mount for DirMount {
+    fn kind(&self, name: &str) -> Option<EntryKind> {
+        let metadata = fs::metadata(self.to_path(name)).ok()?;
+        if metadata.is_dir() {
+            Some(EntryKind::Dir)
+        } else {
+            Some(EntryKind::File)
+        }
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let mut file = fs::File::open(self.to_path(name))?;
+
+        let mut buffer: Vec<u8> = Vec::with_capacity(file.metadata()?.len() as usize + 1);
+        file.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn modified(&self, name: &str) -> io::Result<SystemTime> {
+        fs::metadata(self.to_path(name))?.modified()
+    }
+
+    fn list_dir(&self, name: &str) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.to_path(name))? {
+            // names that are not UTF-8 cannot be resource names
+            if let Ok(file_name) = entry?.file_name().into_string() {
+                names.push(file_name);
+            }
+        }
+        Ok(names)
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        Some(self.to_path(name))
+    }
+}
+
+/// Files held in memory, keyed by resource name. Asset packs are loaded into
+/// one of these when mounted; tests can build one by hand.
+pub struct MemoryMount {
+    files: BTreeMap<String, MemoryFile>,
+}
+
+struct MemoryFile {
+    contents: Vec<u8>,
+    modified: SystemTime,
+}
+
+impl MemoryMount {
+    pub fn new() -> MemoryMount {
+        MemoryMount {
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Adds or replaces a file; only its own modification time changes.
+    pub fn insert(&mut self, name: &str, contents: Vec<u8>) -> Result<(), Error> {
+        self.insert_at(name, contents, SystemTime::now())
+    }
+
+    fn insert_at(
+        &mut self,
+        name: &str,
+        contents: Vec<u8>,
+        modified: SystemTime,
+    ) -> Result<(), Error> {
+        let name = normalize_name(name)?;
+        if name.is_empty() {
+            return Err(Error::InvalidResourceName {
+                name,
+                reason: "name is empty",
+            });
+        }
+        self.files.insert(name, MemoryFile { contents, modified });
+        Ok(())
+    }
+
+    pub fn with(mut self, name: &str, contents: Vec<u8>) -> Result<MemoryMount, Error> {
+        self.insert(name, contents)?;
+        Ok(self)
+    }
+
+    /// Loads every regular file of a `.tar` archive. Each file is stamped
+    /// with the archive's modification time.
+    pub fn from_tar(path: &Path) -> Result<MemoryMount, Error> {
+        let invalid = |e: io::Error| Error::InvalidPack {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        };
+
+        let file = fs::File::open(path)?;
+        let metadata = file.metadata()?;
+        let (modified, len) = (metadata.modified()?, metadata.len());
+        let mut archive = tar::Archive::new(file);
+        let mut mount = MemoryMount::new();
+        for entry in archive.entries().map_err(invalid)? {
+            let mut entry = entry.map_err(invalid)?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = match entry.path().map_err(invalid)?.to_str() {
+                Some(name) => name.to_owned(),
+                None => continue,
+            };
+            // a header can claim any size, so reserve no more than the
+            // archive itself holds
+            let mut contents = Vec::with_capacity(entry.size().min(len) as usize);
+            entry.read_to_end(&mut contents).map_err(invalid)?;
+            check_entry_size(path, &name, &contents, entry.size())?;
+            mount.insert_pack_entry(path, &name, contents, modified)?;
+        }
+        Ok(mount)
+    }
+
+    /// Loads every file of a `.zip` archive. Only stored and deflated
+    /// entries are supported. Each file is stamped with the archive's
+    /// modification time.
+    pub fn from_zip(path: &Path) -> Result<MemoryMount, Error> {
+        let invalid = |e: zip::result::ZipError| Error::InvalidPack {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        };
+
+        let file = fs::File::open(path)?;
+        let metadata = file.metadata()?;
+        let (modified, len) = (metadata.modified()?, metadata.len());
+        let mut archive = zip::ZipArchive::new(file).map_err(invalid)?;
+        let mut mount = MemoryMount::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).map_err(invalid)?;
+            if file.is_dir() {
+                continue;
+            }
+            let name = file.name().to_owned();
+            // deflated entries may be larger than the archive, but the
+            // reservation is only a hint; reading grows it as needed
+            let mut contents = Vec::with_capacity(file.size().min(len) as usize);
+            file.read_to_end(&mut contents)
+                .map_err(|e| invalid(e.into()))?;
+            check_entry_size(path, &name, &contents, file.size())?;
+            mount.insert_pack_entry(path, &name, contents, modified)?;
+        }
+        Ok(mount)
+    }
+
+    fn insert_pack_entry(
+        &mut self,
+        path: &Path,
+        name: &str,
+        contents: Vec<u8>,
+        modified: SystemTime,
+    ) -> Result<(), Error> {
+        // archivers commonly prefix entries with "./"
+        let name = name.trim_start_matches("./");
+        self.insert_at(name, contents, modified)
+            .map_err(|e| Error::InvalidPack {
+                path: path.display().to_string(),
+                message: e.to_string(),
+            })
+    }
+}
+
+fn check_entry_size(path: &Path, name: &str, contents: &[u8], size: u64) -> Result<(), Error> {
+    if contents.len() as u64 != size {
+        return Err(Error::InvalidPack {
+            path: path.display().to_string(),
+            message: format!(
+                "entry {} holds {} bytes, its header says {}",
+                name,
+                contents.len(),
+                size
+            ),
+        });
+    }
+    Ok(())
+}
+
+impl Default for MemoryMount {
+    fn default() -> Self {
+        MemoryMount::new()
+    }
+}
+
+impl Mount for MemoryMount {
+    fn kind(&self, name: &str) -> Option<EntryKind> {
+        if self.files.contains_key(name) {
+            return Some(EntryKind::File);
+        }
+        if name.is_empty() {
+            return Some(EntryKind::Dir);
+        }
+        let prefix = format!("{}/", name);
+        self.files
+            .range(prefix.clone()..)
+            .next()
+            .filter(|(file, _)| file.starts_with(&prefix))
+            .map(|_| EntryKind::Dir)
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.files
+            .get(name)
+            .map(|file| file.contents.clone())
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn modified(&self, name: &str) -> io::Result<SystemTime> {
+        self.files
+            .get(name)
+            .map(|file| file.modified)
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn list_dir(&self, name: &str) -> io::Result<Vec<String>> {
+        let prefix = if name.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", name)
+        };
+        let mut names: Vec<String> = self
+            .files
+            .range(prefix.clone()..)
+            .take_while(|(file, _)| file.starts_with(&prefix))
+            .filter_map(|(file, _)| file[prefix.len()..].split('/').next())
+            .map(|entry| entry.to_owned())
+            .collect();
+        names.dedup();
+        Ok(names)
+    }
+}
+
+/// A sorted `(resource name, contents)` table compiled into the binary.
+#[cfg(feature = "embedded_assets")]
+pub struct EmbeddedMount {
+    files: &'static [(&'static str, &'static [u8])],
+}
+
+#[cfg(feature = "embedded_assets")]
+impl EmbeddedMount {
+    pub fn new(files: &'static [(&'static str, &'static [u8])]) -> EmbeddedMount {
+        EmbeddedMount { files }
+    }
+
+    fn get(&self, name: &str) -> Option<&'static [u8]> {
+        self.files
+            .binary_search_by_key(&name, |&(file, _)| file)
+            .ok()
+            .map(|i| self.files[i].1)
+    }
+
+    fn entries<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'static str> + 'a {
+        self.files
+            .iter()
+            .map(|&(file, _)| file)
+            .filter(move |file| file.starts_with(prefix))
+    }
+}
+
+#[cfg(feature = "embedded_assets")]
+impl Mount for EmbeddedMount {
+    fn kind(&self, name: &str) -> Option<EntryKind> {
+        if self.get(name).is_some() {
+            return Some(EntryKind::File);
+        }
+        if name.is_empty() {
+            return Some(EntryKind::Dir);
+        }
+        let prefix = format!("{}/", name);
+        let kind = self.entries(&prefix).next().map(|_| EntryKind::Dir);
+        kind
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.get(name)
+            .map(|contents| contents.to_vec())
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn modified(&self, _name: &str) -> io::Result<SystemTime> {
+        // embedded files never change while the program runs
+        Ok(SystemTime::UNIX_EPOCH)
+    }
+
+    fn list_dir(&self, name: &str) -> io::Result<Vec<String>> {
+        let prefix = if name.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", name)
+        };
+        let mut names: Vec<String> = self
+            .entries(&prefix)
+            .filter_map(|file| file[prefix.len()..].split('/').next())
+            .map(|entry| entry.to_owned())
+            .collect();
+        names.dedup();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
+
+    /// A scratch file that is deleted when the test ends.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> TempFile {
+            let path =
+                std::env::temp_dir().join(format!("opengl-rust-{}-{}", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn mount() -> MemoryMount {
+        MemoryMount::new()
+            .with("shaders/a.vert", b"a".to_vec())
+            .unwrap()
+            .with("shaders/lit/b.frag", b"b".to_vec())
+            .unwrap()
+            .with("shadersx", b"x".to_vec())
+            .unwrap()
+    }
+
+    #[test]
+    fn memory_mount_reports_kinds() {
+        let mount = mount();
+        assert_eq!(mount.kind(""), Some(EntryKind::Dir));
+        assert_eq!(mount.kind("shaders"), Some(EntryKind::Dir));
+        assert_eq!(mount.kind("shaders/lit"), Some(EntryKind::Dir));
+        assert_eq!(mount.kind("shaders/a.vert"), Some(EntryKind::File));
+        assert_eq!(mount.kind("shadersx"), Some(EntryKind::File));
+        assert_eq!(mount.kind("shader"), None);
+        assert_eq!(mount.kind("shaders/a"), None);
+    }
+
+    #[test]
+    fn memory_mount_reads_and_lists() {
+        let mount = mount();
+        assert_eq!(mount.read("shaders/a.vert").unwrap(), b"a");
+        assert_eq!(
+            mount.read("shaders").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(mount.list_dir("").unwrap(), ["shaders", "shadersx"]);
+        assert_eq!(mount.list_dir("shaders").unwrap(), ["a.vert", "lit"]);
+        assert!(mount.list_dir("missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn memory_mount_normalizes_and_rejects_names() {
+        let mut mount = MemoryMount::new();
+        mount.insert("./a/../b.txt", b"b".to_vec()).unwrap();
+        assert_eq!(mount.read("b.txt").unwrap(), b"b");
+
+        assert!(mount.insert("", Vec::new()).is_err());
+        assert!(mount.insert(".", Vec::new()).is_err());
+        assert!(mount.insert("../b.txt", Vec::new()).is_err());
+        assert!(mount.insert("/b.txt", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn memory_mount_tracks_modification_per_file() {
+        let mut mount = mount();
+        let a = mount.modified("shaders/a.vert").unwrap();
+        let b = mount.modified("shaders/lit/b.frag").unwrap();
+
+        thread::sleep(Duration::from_millis(10));
+        mount.insert("shaders/c.vert", b"c".to_vec()).unwrap();
+        mount.insert("shaders/a.vert", b"a2".to_vec()).unwrap();
+
+        assert!(mount.modified("shaders/a.vert").unwrap() > a);
+        assert_eq!(mount.modified("shaders/lit/b.frag").unwrap(), b);
+        assert_eq!(
+            mount.modified("missing").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn loads_tar_archives() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for &(name, contents) in &[("./shaders/a.vert", &b"a"[..]), ("b.txt", &b"bb"[..])] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, contents).unwrap();
+        }
+        let file = TempFile::new("pack.tar", &builder.into_inner().unwrap());
+
+        let mount = MemoryMount::from_tar(&file.0).unwrap();
+        assert_eq!(mount.read("shaders/a.vert").unwrap(), b"a");
+        assert_eq!(mount.read("b.txt").unwrap(), b"bb");
+        assert_eq!(mount.kind("shaders"), Some(EntryKind::Dir));
+    }
+
+    #[test]
+    fn loads_zip_archives() {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .add_directory("shaders/", zip::write::FileOptions::default())
+            .unwrap();
+        for &(name, contents) in &[("shaders/a.vert", &b"a"[..]), ("b.txt", &b"bb"[..])] {
+            writer
+                .start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+        let file = TempFile::new("pack.zip", &bytes);
+
+        let mount = MemoryMount::from_zip(&file.0).unwrap();
+        assert_eq!(mount.read("shaders/a.vert").unwrap(), b"a");
+        assert_eq!(mount.read("b.txt").unwrap(), b"bb");
+        assert_eq!(mount.list_dir("").unwrap(), ["b.txt", "shaders"]);
+    }
+
+    #[test]
+    fn rejects_malformed_archives() {
+        let tar = TempFile::new("bad.tar", &[0xff; 512]);
+        assert!(matches!(
+            MemoryMount::from_tar(&tar.0),
+            Err(Error::InvalidPack { .. })
+        ));
+
+        let zip = TempFile::new("bad.zip", b"PK\x03\x04 truncated");
+        assert!(matches!(
+            MemoryMount::from_zip(&zip.0),
+            Err(Error::InvalidPack { .. })
+        ));
+    }
+
+    fn stored_zip(name: &str, contents: &[u8]) -> Vec<u8> {
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file(name, options).unwrap();
+        writer.write_all(contents).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Overwrites a little-endian `u32` field of the local and central
+    /// headers of a single-entry zip.
+    fn patch_zip_headers(bytes: &mut [u8], local_offset: usize, central_offset: usize, value: u32) {
+        let central = bytes.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        bytes[local_offset..local_offset + 4].copy_from_slice(&value.to_le_bytes());
+        bytes[central + central_offset..central + central_offset + 4]
+            .copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_oversized_archive_entries() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1 << 62);
+        header.set_mode(0o644);
+        header.set_path("huge.bin").unwrap();
+        header.set_cksum();
+        builder.append(&header, &[0u8; 1024][..]).unwrap();
+        let file = TempFile::new("huge.tar", &builder.into_inner().unwrap());
+        assert!(matches!(
+            MemoryMount::from_tar(&file.0),
+            Err(Error::InvalidPack { .. })
+        ));
+
+        // the uncompressed size field
+        let mut bytes = stored_zip("huge.bin", b"small");
+        patch_zip_headers(&mut bytes, 22, 24, 0xffff_fff0);
+        let file = TempFile::new("huge.zip", &bytes);
+        assert!(matches!(
+            MemoryMount::from_zip(&file.0),
+            Err(Error::InvalidPack { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupt_zip_entries() {
+        // the CRC-32 field
+        let mut bytes = stored_zip("a.txt", b"contents");
+        patch_zip_headers(&mut bytes, 14, 16, 0);
+        let file = TempFile::new("crc.zip", &bytes);
+        assert!(matches!(
+            MemoryMount::from_zip(&file.0),
+            Err(Error::InvalidPack { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_archive_entries_outside_the_root() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        header.set_mode(0o644);
+        // append_data refuses `..`, so write the name into the header directly
+        header.as_old_mut().name[..8].copy_from_slice(b"../x.txt");
+        header.set_cksum();
+        builder.append(&header, &b"x"[..]).unwrap();
+        let file = TempFile::new("escape.tar", &builder.into_inner().unwrap());
+
+        assert!(matches!(
+            MemoryMount::from_tar(&file.0),
+            Err(Error::InvalidPack { .. })
+        ));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::resources::Resources;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub name: String,
+    pub kind: ChangeKind,
+}
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+// a change is seen by one poll and can only be reported by a later one, so a
+// debounce shorter than the poll interval would merge nothing
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches a resource, or every file below a resource directory, for
+/// changes. Created by `Resources::subscribe`.
+///
+/// Changes are found by comparing modification times from `poll`, which is
+/// cheap enough to call once per frame. A change is only reported once the
+/// file has stopped changing for the debounce period, so an editor writing a
+/// file in several steps produces a single event.
+pub struct Subscription {
+    name: String,
+    files: BTreeMap<String, SystemTime>,
+    pending: BTreeMap<String, (ChangeKind, Instant)>,
+    poll_interval: Duration,
+    debounce: Duration,
+    last_poll: Instant,
+}
+
+impl Subscription {
+    pub(super) fn new(res: &Resources, name: String) -> Subscription {
+        let mut files = BTreeMap::new();
+        snapshot(res, &name, &mut files);
+        Subscription {
+            name,
+            files,
+            pending: BTreeMap::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            debounce: DEFAULT_DEBOUNCE,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.debounce = debounce;
+    }
+
+    /// Returns the changes that have settled since the last call, sorted by
+    /// resource name.
+    pub fn poll(&mut self, res: &Resources) -> Vec<Change> {
+        let now = Instant::now();
+        if now.duration_since(self.last_poll) < self.poll_interval {
+            return Vec::new();
+        }
+        self.last_poll = now;
+
+        let mut files = BTreeMap::new();
+        snapshot(res, &self.name, &mut files);
+        for (name, modified) in files.iter() {
+            match self.files.get(name) {
+                None => self.record(name, ChangeKind::Created, now),
+                Some(previous) if previous != modified => {
+                    self.record(name, ChangeKind::Modified, now)
+                }
+                Some(_) => {}
+            }
+        }
+        let removed: Vec<String> = self
+            .files
+            .keys()
+            .filter(|name| !files.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.record(&name, ChangeKind::Removed, now);
+        }
+        self.files = files;
+
+        let debounce = self.debounce;
+        let settled: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, changed))| now.duration_since(*changed) >= debounce)
+            .map(|(name, _)| name.clone())
+            .collect();
+        settled
+            .into_iter()
+            .filter_map(|name| {
+                let (kind, _) = self.pending.remove(&name)?;
+                Some(Change { name, kind })
+            })
+            .collect()
+    }
+
+    /// Folds a new change into the one still waiting to settle, if any.
+    fn record(&mut self, name: &str, kind: ChangeKind, now: Instant) {
+        let previous = self.pending.get(name).map(|&(kind, _)| kind);
+        let kind = match (previous, kind) {
+            (None, kind) => kind,
+            (Some(ChangeKind::Created), ChangeKind::Modified) => ChangeKind::Created,
+            (Some(ChangeKind::Created), ChangeKind::Removed) => {
+                // never seen by the subscriber, so nothing to report
+                self.pending.remove(name);
+                return;
+            }
+            (Some(ChangeKind::Removed), ChangeKind::Created) => ChangeKind::Modified,
+            (Some(_), kind) => kind,
+        };
+        self.pending.insert(name.into(), (kind, now));
+    }
+}
+
+/// Collects the modification time of `name`, or of every file below it if
+/// it is a directory.
+fn snapshot(res: &Resources, name: &str, files: &mut BTreeMap<String, SystemTime>) {
+    match res.list_dir(name) {
+        Ok(entries) => {
+            for entry in entries {
+                snapshot(res, &entry, files);
+            }
+        }
+        Err(_) => {
+            if let Ok(modified) = res.modified(name) {
+                files.insert(name.into(), modified);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    const SETTLE: Duration = Duration::from_millis(50);
+
+    /// A scratch resource directory that is deleted when the test ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path =
+                std::env::temp_dir().join(format!("opengl-rust-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(path.join("shaders")).unwrap();
+            TempDir(path)
+        }
+
+        fn resources(&self) -> Resources {
+            Resources::from_root(&self.0).unwrap()
+        }
+
+        fn write(&self, name: &str, contents: &str) {
+            // keeps successive writes apart on coarse file system clocks
+            thread::sleep(Duration::from_millis(10));
+            fs::write(self.0.join(name), contents).unwrap();
+        }
+
+        fn remove(&self, name: &str) {
+            fs::remove_file(self.0.join(name)).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn subscribe(res: &Resources, name: &str) -> Subscription {
+        let mut subscription = res.subscribe(name).unwrap();
+        subscription.set_poll_interval(Duration::from_millis(0));
+        subscription.set_debounce(SETTLE);
+        subscription
+    }
+
+    fn change(name: &str, kind: ChangeKind) -> Change {
+        Change {
+            name: name.into(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn default_debounce_spans_a_poll() {
+        let dir = TempDir::new("watch-default");
+        let subscription = dir.resources().subscribe("shaders").unwrap();
+        assert!(subscription.debounce >= subscription.poll_interval);
+    }
+
+    #[test]
+    fn reports_changes_once_settled() {
+        let dir = TempDir::new("watch-settle");
+        let res = dir.resources();
+        let mut subscription = subscribe(&res, "shaders");
+
+        dir.write("shaders/a.vert", "a");
+        assert!(subscription.poll(&res).is_empty());
+        thread::sleep(SETTLE);
+        assert_eq!(
+            subscription.poll(&res),
+            [change("shaders/a.vert", ChangeKind::Created)]
+        );
+        assert!(subscription.poll(&res).is_empty());
+    }
+
+    #[test]
+    fn reports_modification_and_removal() {
+        let dir = TempDir::new("watch-modify");
+        dir.write("shaders/a.vert", "a");
+        let res = dir.resources();
+        let mut subscription = subscribe(&res, "shaders/a.vert");
+
+        dir.write("shaders/a.vert", "b");
+        subscription.poll(&res);
+        thread::sleep(SETTLE);
+        assert_eq!(
+            subscription.poll(&res),
+            [change("shaders/a.vert", ChangeKind::Modified)]
+        );
+
+        dir.remove("shaders/a.vert");
+        subscription.poll(&res);
+        thread::sleep(SETTLE);
+        assert_eq!(
+            subscription.poll(&res),
+            [change("shaders/a.vert", ChangeKind::Removed)]
+        );
+    }
+
+    #[test]
+    fn merges_changes_before_they_settle() {
+        let dir = TempDir::new("watch-merge");
+        let res = dir.resources();
+        let mut subscription = subscribe(&res, "shaders");
+
+        dir.write("shaders/a.vert", "a");
+        subscription.poll(&res);
+        dir.write("shaders/a.vert", "b");
+        subscription.poll(&res);
+        dir.write("shaders/b.vert", "b");
+        subscription.poll(&res);
+        dir.remove("shaders/b.vert");
+        subscription.poll(&res);
+
+        thread::sleep(SETTLE);
+        assert_eq!(
+            subscription.poll(&res),
+            [change("shaders/a.vert", ChangeKind::Created)]
+        );
+    }
+
+    #[test]
+    fn waits_for_the_poll_interval() {
+        let dir = TempDir::new("watch-interval");
+        let res = dir.resources();
+        let mut subscription = subscribe(&res, "shaders");
+        subscription.set_poll_interval(Duration::from_secs(3600));
+        subscription.set_debounce(Duration::from_millis(0));
+
+        dir.write("shaders/a.vert", "a");
+        assert!(subscription.poll(&res).is_empty());
+    }
+
+    #[test]
+    fn watches_names_that_do_not_exist_yet() {
+        let dir = TempDir::new("watch-missing");
+        let res = dir.resources();
+        let mut subscription = subscribe(&res, "missing/a.vert");
+        assert_eq!(subscription.name(), "missing/a.vert");
+
+        fs::create_dir(dir.0.join("missing")).unwrap();
+        dir.write("missing/a.vert", "a");
+        subscription.poll(&res);
+        thread::sleep(SETTLE);
+        assert_eq!(
+            subscription.poll(&res),
+            [change("missing/a.vert", ChangeKind::Created)]
+        );
+    }
+}